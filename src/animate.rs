@@ -0,0 +1,150 @@
+//! Smooth marble movement between simulation ticks.
+//!
+//! Each tick, every marble gets a [`MarbleMotion`] describing its hop, and the
+//! sprite follows it as the tick timer runs. Because progress comes from the
+//! tick timer, pausing freezes marbles mid-hop and resuming carries on.
+
+use bevy::prelude::*;
+
+use crate::grid::{GRID_UNITS_PER_TILE, GridPosition};
+
+/// The path a marble follows during one tick.
+#[derive(Copy, Clone, Debug, PartialEq, Component)]
+pub struct MarbleMotion {
+    from: Vec2,
+    to: Vec2,
+    /// The control point of a quadratic Bézier curve, for hops that bend.
+    control: Option<Vec2>,
+}
+
+impl MarbleMotion {
+    /// A marble that stays where it is.
+    pub fn stationary(position: GridPosition) -> Self {
+        let position = position.to_world();
+        Self {
+            from: position,
+            to: position,
+            control: None,
+        }
+    }
+
+    /// A hop between two grid positions.
+    ///
+    /// A hop that starts and ends on the same side of a tile row is a U-turn
+    /// (as in Turn and LongTurn), so it bends through the middle of the tile,
+    /// reaching the opposite side of the row halfway along. Every other hop
+    /// is a straight line.
+    pub fn new(from: GridPosition, to: GridPosition) -> Self {
+        let GridPosition(start) = from;
+        let GridPosition(end) = to;
+        let control = (start.y == end.y && start.x != end.x).then(|| {
+            let row = start.y.div_euclid(GRID_UNITS_PER_TILE) * GRID_UNITS_PER_TILE;
+            let middle = row + GRID_UNITS_PER_TILE / 2;
+            // A quadratic curve reaches a quarter of the way from the ends to
+            // the control point, so this peaks at the same distance on the
+            // other side of the middle.
+            let control_y = start.y + 4 * (middle - start.y);
+            GridPosition(IVec2::new((start.x + end.x) / 2, control_y)).to_world()
+        });
+        Self {
+            from: from.to_world(),
+            to: to.to_world(),
+            control,
+        }
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.from != self.to
+    }
+
+    /// Returns `true` if the path is a straight line.
+    pub fn is_straight(&self) -> bool {
+        self.control.is_none()
+    }
+
+    /// The position along the path, for `t` from 0 to 1.
+    pub fn point(&self, t: f32) -> Vec2 {
+        match self.control {
+            Some(control) => {
+                let a = self.from.lerp(control, t);
+                let b = control.lerp(self.to, t);
+                a.lerp(b, t)
+            }
+            None => self.from.lerp(self.to, t),
+        }
+    }
+
+    /// The direction of travel along the path, for `t` from 0 to 1.
+    pub fn direction(&self, t: f32) -> Vec2 {
+        let direction = match self.control {
+            Some(control) => (control - self.from).lerp(self.to - control, t),
+            None => self.to - self.from,
+        };
+        direction.normalize_or_zero()
+    }
+}
+
+/// Ease in and out, so that marbles roll smoothly from one hop to the next.
+pub fn ease(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Move the marble sprites along their paths.
+///
+/// `progress` is how far through the current tick we are, from 0 to 1.
+pub fn place_marbles(progress: f32, marbles: &mut Query<(&mut Transform, &MarbleMotion)>) {
+    let t = ease(progress);
+    for (mut transform, motion) in marbles {
+        let position = motion.point(t);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        if motion.is_moving() {
+            // The sprite's "up" faces the direction of travel.
+            let direction = motion.direction(t);
+            let angle = direction.to_angle() - std::f32::consts::FRAC_PI_2;
+            transform.rotation = Quat::from_rotation_z(angle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::ivec2;
+
+    use super::*;
+
+    #[test]
+    fn straight_hop() {
+        let motion = MarbleMotion::new(GridPosition(ivec2(2, 3)), GridPosition(ivec2(2, 5)));
+        assert_eq!(motion.point(0.0), vec2(8.0, 12.0));
+        assert_eq!(motion.point(0.5), vec2(8.0, 16.0));
+        assert_eq!(motion.point(1.0), vec2(8.0, 20.0));
+        assert_eq!(motion.direction(0.3), Vec2::Y);
+    }
+
+    #[test]
+    fn u_turn() {
+        // A Turn at the origin takes marbles from one bottom lane to the other.
+        let motion = MarbleMotion::new(GridPosition(ivec2(2, 1)), GridPosition(ivec2(6, 1)));
+        assert_eq!(motion.point(0.0), GridPosition(ivec2(2, 1)).to_world());
+        assert_eq!(motion.point(1.0), GridPosition(ivec2(6, 1)).to_world());
+        // Halfway along, the marble is at the top of the tile.
+        assert_eq!(motion.point(0.5), GridPosition(ivec2(4, 3)).to_world());
+        assert_eq!(motion.direction(0.0), Vec2::new(1.0, 2.0).normalize());
+        assert_eq!(motion.direction(0.5), Vec2::X);
+
+        // Flipped vertically, the turn bends downwards.
+        let motion = MarbleMotion::new(GridPosition(ivec2(2, 7)), GridPosition(ivec2(6, 7)));
+        assert_eq!(motion.point(0.5), GridPosition(ivec2(4, 5)).to_world());
+    }
+
+    #[test]
+    fn easing() {
+        assert_eq!(ease(0.0), 0.0);
+        assert_eq!(ease(0.5), 0.5);
+        assert_eq!(ease(1.0), 1.0);
+        assert_eq!(ease(2.0), 1.0);
+        assert!(ease(0.1) < 0.1);
+    }
+}
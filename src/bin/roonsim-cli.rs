@@ -0,0 +1,131 @@
+//! Run a saved board without opening a window, and print the outcome as JSON.
+//!
+//! ```text
+//! roonsim-cli <board.ron> [--ticks N] [--record FILE] [--scale N] [--rate N] [--assets DIR]
+//! ```
+//!
+//! The simulation stops when every marble has stopped, or after N ticks.
+//!
+//! With `--record`, the run is also saved as an animated GIF or APNG, picked by
+//! the file's extension. `--scale` and `--rate` set its size and ticks per
+//! second, and the sprites are loaded from `--assets` (`assets` by default).
+//! The recording runs to the same tick limit as the report.
+
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use roonsim::{
+    board::Board,
+    export::Sprites,
+    record::{self, AnimationFormat, RecordOptions},
+    report::Report,
+    tile::ALL_TILES,
+};
+
+/// The default tick limit, for boards whose marbles never stop.
+const DEFAULT_MAX_TICKS: u32 = 100_000;
+
+const USAGE: &str = "usage: roonsim-cli <board.ron> [--ticks N] [--record FILE] [--scale N] [--rate N] [--assets DIR]";
+
+/// The command line options.
+struct Args {
+    path: String,
+    max_ticks: Option<u32>,
+    record: Option<PathBuf>,
+    options: RecordOptions,
+    assets: PathBuf,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let path = &args.path;
+
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("failed to read {path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let board = match Board::from_ron(&text) {
+        Ok(board) => board,
+        Err(e) => {
+            eprintln!("failed to load {path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let max_ticks = args.max_ticks.unwrap_or(DEFAULT_MAX_TICKS);
+    let report = Report::run(&board, max_ticks);
+
+    if let Some(record_path) = &args.record
+        && let Err(message) = record_run(&board, record_path, max_ticks, &args)
+    {
+        eprintln!("failed to record {}: {message}", record_path.display());
+        return ExitCode::FAILURE;
+    }
+    if args.record.is_some() && !report.quiescent {
+        eprintln!("the recording stops after {max_ticks} ticks, before every marble has stopped");
+    }
+
+    println!("{}", report.to_json());
+    ExitCode::SUCCESS
+}
+
+/// Record a run of the board to an animation file, stopping after `max_ticks`.
+fn record_run(board: &Board, path: &Path, max_ticks: u32, args: &Args) -> Result<(), String> {
+    let format = AnimationFormat::from_path(path).ok_or("the file must end in .gif or .apng")?;
+    let sprites = Sprites::load(&args.assets, ALL_TILES.iter().copied())
+        .map_err(|e| format!("can't load sprites from {}: {e}", args.assets.display()))?;
+    let options = RecordOptions {
+        max_ticks,
+        ..args.options
+    };
+    let contents = record::record(board, &sprites, format, options).map_err(|e| e.to_string())?;
+    std::fs::write(path, contents).map_err(|e| e.to_string())
+}
+
+/// Parse a whole number option.
+fn parse_number(option: &str, args: &mut impl Iterator<Item = String>) -> Result<u32, String> {
+    let value = args.next().ok_or(format!("{option} needs a value"))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value \"{value}\" for {option}"))
+}
+
+/// Parse the board path and options from the command line.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut path = None;
+    let mut max_ticks = None;
+    let mut record = None;
+    let mut options = RecordOptions::default();
+    let mut assets = PathBuf::from("assets");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ticks" => max_ticks = Some(parse_number(&arg, &mut args)?),
+            "--scale" => options.scale = parse_number(&arg, &mut args)?,
+            "--rate" => options.ticks_per_second = parse_number(&arg, &mut args)?,
+            "--record" => record = Some(args.next().ok_or("--record needs a file")?.into()),
+            "--assets" => assets = args.next().ok_or("--assets needs a directory")?.into(),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {arg}")),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+    let path = path.ok_or("no board file given")?;
+    Ok(Args {
+        path,
+        max_ticks,
+        record,
+        options,
+        assets,
+    })
+}
@@ -0,0 +1,456 @@
+//! Blueprints: groups of tiles saved to disk, to be stamped onto boards again.
+//!
+//! In the select tool, Ctrl+B or the "+BP" button asks for a name, then saves
+//! the selected tiles and their marbles to `blueprints/<name>.ron`, in the
+//! board file format. Blueprints are shared, so a name that is already taken
+//! is refused rather than overwritten. Each blueprint gets a button in the bottom row of the UI
+//! panel. Clicking one shows the blueprint as a ghost under the cursor, like a
+//! single tile; ArrowLeft and ArrowUp mirror it, and each click on the board
+//! stamps a copy.
+
+use std::path::{Path, PathBuf};
+
+use bevy::{
+    input::keyboard::{Key, KeyboardInput},
+    prelude::*,
+};
+
+use crate::{
+    MainCamera, MouseClick, SimState,
+    board::Board,
+    grid::GridPosition,
+    history::{Edit, EditBoard},
+    place_tile::DespawnGhostTile,
+    puzzle_mode::ActivePuzzle,
+    save_load::{read_file, write_file},
+    select::{
+        BoardTiles, Selection, collides, inventory_allows, place_group, read_tiles,
+        renumber_marbles, selected_group,
+    },
+    tile::{Marble, Offset},
+    ui::{UiBlueprintPrompt, UiBlueprintRow, ui_blueprint_button},
+};
+
+/// The directory that blueprints are saved in.
+const BLUEPRINT_DIR: &str = "blueprints";
+
+/// The longest blueprint name that can be typed.
+const MAX_NAME_LEN: usize = 24;
+
+/// How opaque the ghost of a blueprint is.
+const GHOST_ALPHA: f32 = 0.3;
+
+pub struct BlueprintPlugin;
+
+impl Plugin for BlueprintPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Blueprints>()
+            .add_systems(Startup, load_blueprints)
+            .add_observer(name_blueprint)
+            .add_observer(stamp_blueprint)
+            .add_systems(
+                Update,
+                (
+                    blueprint_keyboard.run_if(
+                        in_state(SimState::Selecting)
+                            .and(not(resource_exists::<BlueprintNamePrompt>)),
+                    ),
+                    type_blueprint_name.run_if(resource_exists::<BlueprintNamePrompt>),
+                    show_blueprints.run_if(resource_changed::<Blueprints>),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    stamping_keyboard,
+                    ghost_blueprint_cursor_moved,
+                    mouseclick_stamp,
+                )
+                    .run_if(in_state(SimState::Stamping)),
+            )
+            .add_systems(OnExit(SimState::Selecting), cancel_naming)
+            .add_systems(OnExit(SimState::Stamping), despawn_ghost_blueprint);
+    }
+}
+
+/// A named group of tiles, moved so that it starts at the origin.
+pub struct Blueprint {
+    pub name: String,
+    pub board: Board,
+}
+
+/// The blueprints in the blueprint directory, sorted by name.
+#[derive(Resource, Default)]
+pub struct Blueprints(pub Vec<Blueprint>);
+
+impl Blueprints {
+    /// Add a blueprint, replacing any with the same name.
+    fn insert(&mut self, blueprint: Blueprint) {
+        self.0.retain(|other| other.name != blueprint.name);
+        self.0.push(blueprint);
+        self.0.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    /// Check whether a blueprint with this name is loaded or saved already.
+    fn name_taken(&self, name: &str) -> bool {
+        self.0.iter().any(|blueprint| blueprint.name == name) || blueprint_path(name).exists()
+    }
+}
+
+/// Present while a blueprint name is being typed, holding the name so far.
+///
+/// Single-key shortcuts are ignored while this exists.
+#[derive(Resource)]
+pub struct BlueprintNamePrompt(String);
+
+/// Ask for a name, and save the selection as a blueprint.
+#[derive(Event)]
+pub struct NameBlueprint;
+
+/// Start stamping the blueprint with this index.
+#[derive(Event)]
+pub struct StampBlueprint(pub usize);
+
+/// The ghost of the blueprint being stamped.
+#[derive(Component)]
+pub struct GhostBlueprint(Board);
+
+fn blueprint_path(name: &str) -> PathBuf {
+    Path::new(BLUEPRINT_DIR).join(format!("{name}.ron"))
+}
+
+/// Check that a character may be used in a blueprint name, which is also its file name.
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+fn load_blueprints(mut blueprints: ResMut<Blueprints>) {
+    for path in list_blueprint_files() {
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let board = read_file(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Board::from_ron(&text).map_err(|e| e.to_string()));
+        match board {
+            Ok(board) => blueprints.insert(Blueprint {
+                name: name.to_owned(),
+                board: board.shifted_to_origin(),
+            }),
+            Err(e) => error!("failed to load blueprint {}: {e}", path.display()),
+        }
+    }
+    info!("loaded {} blueprints", blueprints.0.len());
+}
+
+/// Handle the Ctrl+B shortcut.
+fn blueprint_keyboard(keyboard: Res<ButtonInput<KeyCode>>, mut commands: Commands) {
+    if keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+        && keyboard.just_pressed(KeyCode::KeyB)
+    {
+        commands.trigger(NameBlueprint);
+    }
+}
+
+fn name_blueprint(
+    _trigger: Trigger<NameBlueprint>,
+    state: Res<State<SimState>>,
+    selection: Res<Selection>,
+    mut commands: Commands,
+) {
+    if *state.get() != SimState::Selecting || selection.0.is_empty() {
+        info!("select some tiles to save as a blueprint");
+        return;
+    }
+    commands.insert_resource(BlueprintNamePrompt(String::new()));
+}
+
+/// Type the blueprint name. Enter saves the blueprint, and Escape cancels.
+#[expect(clippy::too_many_arguments)]
+fn type_blueprint_name(
+    mut key_reader: EventReader<KeyboardInput>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut prompt: ResMut<BlueprintNamePrompt>,
+    mut blueprints: ResMut<Blueprints>,
+    selection: Res<Selection>,
+    tiles: BoardTiles,
+    marbles: Query<(&GridPosition, &Marble)>,
+    mut text: Query<&mut Text, With<UiBlueprintPrompt>>,
+    mut commands: Commands,
+) {
+    let mut done = false;
+    for event in key_reader.read() {
+        if !event.state.is_pressed() || done {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Enter => {
+                done = true;
+                if prompt.0.is_empty() {
+                    continue;
+                }
+                if blueprints.name_taken(&prompt.0) {
+                    // Keep the prompt open, so another name can be typed.
+                    done = false;
+                    info!("there is already a blueprint called {}", prompt.0);
+                    continue;
+                }
+                let (board, _) = read_tiles(&tiles, &marbles);
+                let group = selected_group(&board, &selection).shifted_to_origin();
+                let path = blueprint_path(&prompt.0);
+                match create_blueprint_dir().and_then(|()| write_file(&path, group.to_ron())) {
+                    Ok(()) => {
+                        info!("saved blueprint to {}", path.display());
+                        blueprints.insert(Blueprint {
+                            name: prompt.0.clone(),
+                            board: group,
+                        });
+                    }
+                    Err(e) => error!("failed to save blueprint to {}: {e}", path.display()),
+                }
+            }
+            Key::Escape => done = true,
+            Key::Backspace => {
+                prompt.0.pop();
+            }
+            // Ignore the B from Ctrl+B, and other shortcuts.
+            Key::Character(chars)
+                if !keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) =>
+            {
+                for c in chars.chars().filter(|&c| is_name_char(c)) {
+                    if prompt.0.len() < MAX_NAME_LEN {
+                        prompt.0.push(c);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let caption = if done {
+        commands.remove_resource::<BlueprintNamePrompt>();
+        String::new()
+    } else if blueprints.name_taken(&prompt.0) {
+        format!("blueprint name: {}_ (taken)", prompt.0)
+    } else {
+        format!("blueprint name: {}_", prompt.0)
+    };
+    for mut text in &mut text {
+        if text.0 != caption {
+            text.0 = caption.clone();
+        }
+    }
+}
+
+/// Stop typing a name when leaving the select tool.
+fn cancel_naming(mut commands: Commands, mut text: Query<&mut Text, With<UiBlueprintPrompt>>) {
+    commands.remove_resource::<BlueprintNamePrompt>();
+    for mut text in &mut text {
+        text.0.clear();
+    }
+}
+
+/// Rebuild the row of blueprint buttons.
+fn show_blueprints(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    blueprints: Res<Blueprints>,
+    row: Single<Entity, With<UiBlueprintRow>>,
+) {
+    commands
+        .entity(*row)
+        .despawn_related::<Children>()
+        .with_children(|parent| {
+            for (index, blueprint) in blueprints.0.iter().enumerate() {
+                ui_blueprint_button(&asset_server, parent, index, &blueprint.name);
+            }
+        });
+}
+
+fn stamp_blueprint(
+    trigger: Trigger<StampBlueprint>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    blueprints: Res<Blueprints>,
+    ghosts: Query<Entity, With<GhostBlueprint>>,
+    mut next_state: ResMut<NextState<SimState>>,
+) {
+    let StampBlueprint(index) = *trigger;
+    let Some(blueprint) = blueprints.0.get(index) else {
+        return;
+    };
+    info!("stamping blueprint {}", blueprint.name);
+    commands.trigger(DespawnGhostTile);
+    for ghost in &ghosts {
+        commands.entity(ghost).despawn();
+    }
+    spawn_ghost_blueprint(
+        &mut commands,
+        &asset_server,
+        blueprint.board.clone(),
+        Vec3::ZERO,
+    );
+    next_state.set(SimState::Stamping);
+}
+
+/// Spawn a translucent copy of a blueprint's tiles and marbles.
+fn spawn_ghost_blueprint(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    board: Board,
+    translation: Vec3,
+) {
+    let tiles = board.tiles.clone();
+    let marbles = board.marbles.clone();
+    commands
+        .spawn((
+            GhostBlueprint(board),
+            Transform::from_translation(translation),
+            Visibility::default(),
+        ))
+        .with_children(|parent| {
+            for sim_tile in tiles {
+                let mut sprite = sim_tile.tile.load_sprite(asset_server);
+                sprite.flip_x = sim_tile.flip_x;
+                sprite.flip_y = sim_tile.flip_y;
+                sprite.color = Color::WHITE.with_alpha(GHOST_ALPHA);
+                let position = sim_tile.extent.origin().to_world().extend(0.0);
+                parent.spawn((sprite, Transform::from_translation(position)));
+            }
+            for placed in marbles {
+                let mut sprite = Marble::load_sprite(asset_server);
+                sprite.color = placed.marble.color.tint().with_alpha(GHOST_ALPHA);
+                let position = placed.position.to_world().extend(0.1);
+                parent.spawn((sprite, Transform::from_translation(position)));
+            }
+        });
+}
+
+/// Where the blueprint's origin goes for a world position.
+///
+/// Blueprints start at an even column, so snapping to one keeps every tile's offset.
+fn stamp_position(world_pos: Vec2) -> GridPosition {
+    GridPosition::from_world_with_offset(world_pos, Offset::Even)
+}
+
+/// Mirror the ghost with the arrow keys, or stop stamping with Escape.
+fn stamping_keyboard(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    ghost: Single<(Entity, &GhostBlueprint, &Transform)>,
+    mut next_state: ResMut<NextState<SimState>>,
+) {
+    if keyboard.just_pressed(KeyCode::Escape) {
+        next_state.set(SimState::Idle);
+        return;
+    }
+    let flip_x = keyboard.just_pressed(KeyCode::ArrowLeft);
+    let flip_y = keyboard.just_pressed(KeyCode::ArrowUp);
+    if !(flip_x || flip_y) {
+        return;
+    }
+    let (entity, GhostBlueprint(board), transform) = *ghost;
+    let board = board.mirrored(flip_x, flip_y).shifted_to_origin();
+    commands.entity(entity).despawn();
+    spawn_ghost_blueprint(&mut commands, &asset_server, board, transform.translation);
+}
+
+fn ghost_blueprint_cursor_moved(
+    mut evr_cursor: EventReader<CursorMoved>,
+    camera: Single<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut ghost: Query<&mut Transform, With<GhostBlueprint>>,
+) {
+    let (camera, camera_transform) = *camera;
+    for cursor_moved in evr_cursor.read() {
+        let Ok(world_pos) = camera.viewport_to_world_2d(camera_transform, cursor_moved.position)
+        else {
+            continue;
+        };
+        let position = stamp_position(world_pos).to_world().extend(0.0);
+        for mut transform in &mut ghost {
+            transform.translation = position;
+        }
+    }
+}
+
+/// Stamp a copy of the blueprint where the board was clicked, if there is room.
+fn mouseclick_stamp(
+    mut event_reader: EventReader<MouseClick>,
+    mut commands: Commands,
+    ghost: Single<&GhostBlueprint>,
+    tiles: BoardTiles,
+    marbles: Query<(&GridPosition, &Marble)>,
+    puzzle: Option<Res<ActivePuzzle>>,
+) {
+    for mouse_click in event_reader.read() {
+        let GridPosition(position) = stamp_position(mouse_click.world_pos);
+        let (board, locked) = read_tiles(&tiles, &marbles);
+        let mut stamped = ghost.0.translated(position);
+        renumber_marbles(&board, &mut stamped);
+        if collides(&board, &Selection(Vec::new()), &stamped) {
+            info!("can't stamp the blueprint there: it would overlap other tiles");
+            continue;
+        }
+        if !inventory_allows(puzzle.as_deref(), &board, &locked, &stamped) {
+            continue;
+        }
+        commands.trigger(EditBoard(Edit::Batch(place_group(&stamped))));
+    }
+}
+
+fn despawn_ghost_blueprint(mut commands: Commands, ghosts: Query<Entity, With<GhostBlueprint>>) {
+    for ghost in &ghosts {
+        commands.entity(ghost).despawn();
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn list_blueprint_files() -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(BLUEPRINT_DIR) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+        .collect()
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn create_blueprint_dir() -> std::io::Result<()> {
+    std::fs::create_dir_all(BLUEPRINT_DIR)
+}
+
+#[cfg(target_family = "wasm")]
+fn list_blueprint_files() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+#[cfg(target_family = "wasm")]
+fn create_blueprint_dir() -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blueprint_names() {
+        assert!("xor-adder_2".chars().all(is_name_char));
+        assert!(!"../evil".chars().all(is_name_char));
+        assert_eq!(blueprint_path("latch"), Path::new("blueprints/latch.ron"));
+    }
+
+    #[test]
+    fn taken_names() {
+        let mut blueprints = Blueprints::default();
+        blueprints.insert(Blueprint {
+            name: "latch".into(),
+            board: Board::default(),
+        });
+        assert!(blueprints.name_taken("latch"));
+        assert!(!blueprints.name_taken("no-such-blueprint"));
+    }
+}
@@ -0,0 +1,637 @@
+//! Board layouts, and the text file format used to save them.
+//!
+//! Boards are saved as RON, with one line per tile so that changes show up
+//! as readable diffs:
+//!
+//! ```text
+//! (
+//!     version: 3,
+//!     tiles: [
+//!         (kind: "path", origin: (0, 0), flip_x: false, flip_y: false),
+//!         (kind: "switch", origin: (-2, 4), flip_x: true, flip_y: false, state: Lever(diverted: true)),
+//!     ],
+//!     marbles: [
+//!         (id: 0, color: "red", position: (2, 3)),
+//!     ],
+//! )
+//! ```
+//!
+//! A tile's `state` is only written when it differs from the state of a newly
+//! placed tile. Version 2 files have no states, so their tiles start in their
+//! initial state.
+//!
+//! Version 1 files list marbles as bare positions, like `(2, 3)`. These are
+//! still accepted: such marbles are white, and numbered in the order they
+//! appear.
+
+use std::fmt::Display;
+
+use bevy::math::IVec2;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::grid::{GRID_UNITS_PER_TILE, GridPosition};
+use crate::sim::{SimTile, Simulation};
+use crate::tile::{Marble, MarbleColor, Offset, Tile, TileState};
+
+/// The file format version written by this version of the program.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// The tiles and marbles on the board.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Board {
+    pub tiles: Vec<SimTile>,
+    /// The marbles, in the same order as the simulation's marbles.
+    pub marbles: Vec<PlacedMarble>,
+}
+
+/// A marble, and where it is on the board.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PlacedMarble {
+    pub position: GridPosition,
+    pub marble: Marble,
+}
+
+impl Board {
+    /// Create a simulation of this board.
+    pub fn simulation(&self) -> Simulation {
+        Simulation::new(
+            self.tiles.clone(),
+            self.marbles.iter().map(|placed| placed.position),
+        )
+    }
+
+    /// Return a copy of the board with every tile and marble moved by `offset` grid units.
+    ///
+    /// The tiles stay on legal positions if [`is_legal_shift`] allows the offset.
+    pub fn translated(&self, offset: IVec2) -> Board {
+        let tiles = self
+            .tiles
+            .iter()
+            .map(|sim_tile| {
+                let GridPosition(origin) = sim_tile.extent.origin();
+                SimTile {
+                    extent: sim_tile.tile.extent(GridPosition(origin + offset)),
+                    ..*sim_tile
+                }
+            })
+            .collect();
+        let marbles = self
+            .marbles
+            .iter()
+            .map(|placed| PlacedMarble {
+                position: GridPosition(placed.position.0 + offset),
+                ..*placed
+            })
+            .collect();
+        Board { tiles, marbles }
+    }
+
+    /// Return a copy of the board moved so that its lowest, leftmost tile is at
+    /// the origin, or one grid unit right of it for tiles with an odd offset.
+    pub fn shifted_to_origin(&self) -> Board {
+        let Some(min) = self
+            .tiles
+            .iter()
+            .map(|sim_tile| sim_tile.extent.origin().0)
+            .reduce(IVec2::min)
+        else {
+            return self.clone();
+        };
+        // Shift by an even amount, so odd-offset tiles stay odd.
+        let min = IVec2::new(min.x - min.x.rem_euclid(2), min.y);
+        self.translated(-min)
+    }
+
+    /// Return a copy of the board mirrored as a whole, as if flipping every tile.
+    ///
+    /// The tiles stay within the same rows, and within the same columns give
+    /// or take a grid unit: mirroring can shift the group by one unit so that
+    /// every tile stays on a legal position.
+    pub fn mirrored(&self, flip_x: bool, flip_y: bool) -> Board {
+        let Some((min, max)) = self
+            .tiles
+            .iter()
+            .map(|sim_tile| {
+                let GridPosition(origin) = sim_tile.extent.origin();
+                let end = origin + IVec2::new(sim_tile.extent.width(), GRID_UNITS_PER_TILE);
+                (origin, end)
+            })
+            .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+        else {
+            return self.clone();
+        };
+        // Positions are reflected about the middle of the group: p becomes sum - p.
+        let mut sum = min + max;
+        sum.x += sum.x.rem_euclid(2);
+        let reflect = |mut pos: IVec2| {
+            if flip_x {
+                pos.x = sum.x - pos.x;
+            }
+            if flip_y {
+                pos.y = sum.y - pos.y;
+            }
+            pos
+        };
+
+        let tiles = self
+            .tiles
+            .iter()
+            .map(|sim_tile| {
+                // The far corner of the tile becomes its origin.
+                let GridPosition(origin) = sim_tile.extent.origin();
+                let corner = reflect(origin);
+                let origin = IVec2::new(
+                    if flip_x {
+                        corner.x - sim_tile.extent.width()
+                    } else {
+                        corner.x
+                    },
+                    if flip_y {
+                        corner.y - GRID_UNITS_PER_TILE
+                    } else {
+                        corner.y
+                    },
+                );
+                SimTile {
+                    extent: sim_tile.tile.extent(GridPosition(origin)),
+                    flip_x: sim_tile.flip_x ^ flip_x,
+                    flip_y: sim_tile.flip_y ^ flip_y,
+                    ..*sim_tile
+                }
+            })
+            .collect();
+        let marbles = self
+            .marbles
+            .iter()
+            .map(|placed| PlacedMarble {
+                position: GridPosition(reflect(placed.position.0)),
+                ..*placed
+            })
+            .collect();
+        Board { tiles, marbles }
+    }
+
+    /// The marbles sitting on a tile.
+    pub fn marbles_on(&self, sim_tile: &SimTile) -> Vec<PlacedMarble> {
+        self.marbles
+            .iter()
+            .copied()
+            .filter(|placed| sim_tile.extent.contains_grid(placed.position))
+            .collect()
+    }
+
+    /// Serialize the board to the text file format.
+    pub fn to_ron(&self) -> String {
+        // Limit the depth so that each tile is written on a single line.
+        let config = ron::ser::PrettyConfig::new().depth_limit(2);
+        let mut text =
+            ron::ser::to_string_pretty(&self.to_file(), config).expect("board serialization");
+        text.push('\n');
+        text
+    }
+
+    /// Serialize the board to the text file format, without any whitespace.
+    pub fn to_compact_ron(&self) -> String {
+        ron::ser::to_string(&self.to_file()).expect("board serialization")
+    }
+
+    fn to_file(&self) -> BoardFile {
+        BoardFile {
+            version: FORMAT_VERSION,
+            tiles: self.tiles.iter().map(TileEntry::from_sim_tile).collect(),
+            marbles: self
+                .marbles
+                .iter()
+                .map(MarbleEntry::from_placed_marble)
+                .collect(),
+        }
+    }
+
+    /// Parse a board from the text file format.
+    pub fn from_ron(text: &str) -> Result<Self, BoardError> {
+        let file: BoardFile = ron::from_str(text).map_err(BoardError::Parse)?;
+        if file.version > FORMAT_VERSION {
+            return Err(BoardError::UnsupportedVersion(file.version));
+        }
+
+        let tiles = parse_tiles(file.tiles)?;
+        let marbles = parse_marbles(file.marbles)?;
+
+        Ok(Self { tiles, marbles })
+    }
+}
+
+/// Convert tile entries to tiles, checking that they are legally placed and don't overlap.
+pub fn parse_tiles(entries: Vec<TileEntry>) -> Result<Vec<SimTile>, BoardError> {
+    let mut tiles: Vec<SimTile> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let tile =
+            Tile::from_name(&entry.kind).ok_or(BoardError::UnknownTile(entry.kind.clone()))?;
+        let origin = GridPosition(entry.origin.into());
+        if !is_legal_origin(tile, origin) {
+            return Err(BoardError::IllegalPosition(tile, origin));
+        }
+        let extent = tile.extent(origin);
+        if tiles.iter().any(|other| other.extent.intersects(&extent)) {
+            return Err(BoardError::Overlap(tile, origin));
+        }
+        let state = entry.state.unwrap_or(tile.initial_state());
+        if !is_valid_state(tile, state) {
+            return Err(BoardError::InvalidState(tile, state));
+        }
+        tiles.push(SimTile {
+            tile,
+            extent,
+            flip_x: entry.flip_x,
+            flip_y: entry.flip_y,
+            state,
+        });
+    }
+    Ok(tiles)
+}
+
+/// Convert marble entries to marbles, numbering any that don't have an id.
+pub fn parse_marbles(entries: Vec<MarbleEntry>) -> Result<Vec<PlacedMarble>, BoardError> {
+    let mut marbles: Vec<PlacedMarble> = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.into_iter().enumerate() {
+        let (position, id, color) = match entry {
+            MarbleEntry::Position(position) => (position, index as u32, MarbleColor::default()),
+            MarbleEntry::Marble {
+                position,
+                id,
+                color,
+            } => {
+                let color = match color {
+                    Some(name) => {
+                        MarbleColor::from_name(&name).ok_or(BoardError::UnknownColor(name))?
+                    }
+                    None => MarbleColor::default(),
+                };
+                (position, id.unwrap_or(index as u32), color)
+            }
+        };
+        if marbles.iter().any(|other| other.marble.id == id) {
+            return Err(BoardError::DuplicateMarbleId(id));
+        }
+        marbles.push(PlacedMarble {
+            position: GridPosition(position.into()),
+            marble: Marble { id, color },
+        });
+    }
+    Ok(marbles)
+}
+
+/// Check that moving a tile by `offset` keeps it on a legal position.
+///
+/// Tiles can only move by whole rows, and by an even number of grid units
+/// horizontally, so that odd-offset tiles stay odd.
+pub fn is_legal_shift(offset: IVec2) -> bool {
+    offset.x.rem_euclid(2) == 0 && offset.y.rem_euclid(GRID_UNITS_PER_TILE) == 0
+}
+
+/// Check that a tile origin is on a tile row, with the right horizontal alignment.
+fn is_legal_origin(tile: Tile, GridPosition(origin): GridPosition) -> bool {
+    let odd = origin.x.rem_euclid(2) == 1;
+    let on_row = origin.y.rem_euclid(GRID_UNITS_PER_TILE) == 0;
+    on_row && (odd == (tile.offset() == Offset::Odd))
+}
+
+/// Check that a tile can be in a state, by cycling through its states.
+fn is_valid_state(tile: Tile, state: TileState) -> bool {
+    let initial = tile.initial_state();
+    let mut candidate = initial;
+    loop {
+        if candidate == state {
+            return true;
+        }
+        candidate = candidate.next();
+        if candidate == initial {
+            return false;
+        }
+    }
+}
+
+/// An error loading a board file.
+#[derive(Debug)]
+pub enum BoardError {
+    Parse(ron::error::SpannedError),
+    UnsupportedVersion(u32),
+    UnknownTile(String),
+    IllegalPosition(Tile, GridPosition),
+    Overlap(Tile, GridPosition),
+    InvalidState(Tile, TileState),
+    UnknownColor(String),
+    DuplicateMarbleId(u32),
+}
+
+impl Display for BoardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BoardError::Parse(e) => write!(f, "parse error: {e}"),
+            BoardError::UnsupportedVersion(version) => {
+                write!(f, "unsupported file version {version}")
+            }
+            BoardError::UnknownTile(name) => write!(f, "unknown tile \"{name}\""),
+            BoardError::IllegalPosition(tile, pos) => {
+                write!(f, "{} can't be placed at {pos}", tile.name())
+            }
+            BoardError::Overlap(tile, pos) => {
+                write!(f, "{} at {pos} overlaps another tile", tile.name())
+            }
+            BoardError::InvalidState(tile, state) => {
+                write!(f, "{} can't be in state {state:?}", tile.name())
+            }
+            BoardError::UnknownColor(name) => write!(f, "unknown marble colour \"{name}\""),
+            BoardError::DuplicateMarbleId(id) => write!(f, "more than one marble has id {id}"),
+        }
+    }
+}
+
+impl std::error::Error for BoardError {}
+
+#[derive(Serialize, Deserialize)]
+struct BoardFile {
+    version: u32,
+    tiles: Vec<TileEntry>,
+    #[serde(default)]
+    marbles: Vec<MarbleEntry>,
+}
+
+/// A tile as written in a file.
+#[derive(Serialize, Deserialize)]
+pub struct TileEntry {
+    kind: String,
+    origin: (i32, i32),
+    #[serde(default)]
+    flip_x: bool,
+    #[serde(default)]
+    flip_y: bool,
+    /// Missing when the tile is in its initial state.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_some",
+        deserialize_with = "deserialize_some"
+    )]
+    state: Option<TileState>,
+}
+
+impl TileEntry {
+    pub fn from_sim_tile(sim_tile: &SimTile) -> Self {
+        let GridPosition(origin) = sim_tile.extent.origin();
+        Self {
+            kind: sim_tile.tile.name().to_owned(),
+            origin: origin.into(),
+            flip_x: sim_tile.flip_x,
+            flip_y: sim_tile.flip_y,
+            state: (sim_tile.state != sim_tile.tile.initial_state()).then_some(sim_tile.state),
+        }
+    }
+}
+
+/// A marble as written in a file.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MarbleEntry {
+    /// A white marble, as written by version 1.
+    Position((i32, i32)),
+    Marble {
+        #[serde(serialize_with = "serialize_some")]
+        id: Option<u32>,
+        #[serde(serialize_with = "serialize_some")]
+        color: Option<String>,
+        position: (i32, i32),
+    },
+}
+
+/// Write an optional field without `Some(...)` around it.
+///
+/// Only used for fields that are always present when writing, or skipped when missing.
+fn serialize_some<T: Serialize, S: Serializer>(
+    value: &Option<T>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    value
+        .as_ref()
+        .expect("optional field is set when writing")
+        .serialize(serializer)
+}
+
+/// Read an optional field that is written without `Some(...)` around it.
+fn deserialize_some<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<T>, D::Error> {
+    T::deserialize(deserializer).map(Some)
+}
+
+impl MarbleEntry {
+    pub fn from_placed_marble(placed: &PlacedMarble) -> Self {
+        let GridPosition(position) = placed.position;
+        Self::Marble {
+            id: Some(placed.marble.id),
+            color: Some(placed.marble.color.name().to_owned()),
+            position: position.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::ivec2;
+
+    use super::*;
+
+    fn place(tile: Tile, x: i32, y: i32, flip_x: bool, flip_y: bool) -> SimTile {
+        SimTile {
+            tile,
+            extent: tile.extent(GridPosition(ivec2(x, y))),
+            flip_x,
+            flip_y,
+            state: tile.initial_state(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let board = Board {
+            tiles: vec![
+                place(Tile::Path, 0, 0, false, false),
+                place(Tile::Switch, -2, 4, true, false),
+                place(Tile::Shimmy, 9, -4, false, true),
+            ],
+            marbles: vec![
+                marble(2, 3, 0, MarbleColor::White),
+                marble(-4, 6, 7, MarbleColor::Blue),
+            ],
+        };
+        let text = board.to_ron();
+        assert!(text.contains(r#"(kind: "switch", origin: (-2, 4), flip_x: true, flip_y: false)"#));
+        assert!(text.contains(r#"(id: 7, color: "blue", position: (-4, 6))"#));
+        assert_eq!(Board::from_ron(&text).unwrap(), board);
+    }
+
+    #[test]
+    fn tile_states() {
+        let mut distributor = place(Tile::Distributor, 0, 0, false, false);
+        distributor.state = TileState::Distributor { next: 2 };
+        let board = Board {
+            tiles: vec![distributor, place(Tile::Switch, 0, 4, false, false)],
+            marbles: Vec::new(),
+        };
+        let text = board.to_ron();
+        assert!(text.contains("state: Distributor(next: 2)"));
+        // Tiles in their initial state don't list it.
+        assert_eq!(text.matches("state:").count(), 1);
+        assert_eq!(Board::from_ron(&text).unwrap(), board);
+        assert_eq!(Board::from_ron(&board.to_compact_ron()).unwrap(), board);
+
+        // Version 2 files have no states.
+        let old = r#"(version: 2, tiles: [(kind: "switch", origin: (0, 0))])"#;
+        assert_eq!(
+            Board::from_ron(old).unwrap().tiles[0].state,
+            Tile::Switch.initial_state()
+        );
+
+        let wrong_kind =
+            r#"(version: 3, tiles: [(kind: "path", origin: (0, 0), state: Xor(odd: true))])"#;
+        assert!(matches!(
+            Board::from_ron(wrong_kind),
+            Err(BoardError::InvalidState(Tile::Path, _))
+        ));
+        let out_of_range = r#"(version: 3, tiles: [
+            (kind: "distributor", origin: (0, 0), state: Distributor(next: 3)),
+        ])"#;
+        assert!(matches!(
+            Board::from_ron(out_of_range),
+            Err(BoardError::InvalidState(Tile::Distributor, _))
+        ));
+    }
+
+    fn marble(x: i32, y: i32, id: u32, color: MarbleColor) -> PlacedMarble {
+        PlacedMarble {
+            position: GridPosition(ivec2(x, y)),
+            marble: Marble { id, color },
+        }
+    }
+
+    #[test]
+    fn marble_entries() {
+        // Version 1 marbles are white, and numbered in order.
+        let old = "(version: 1, tiles: [], marbles: [(2, 3), (-4, 6)])";
+        assert_eq!(
+            Board::from_ron(old).unwrap().marbles,
+            vec![
+                marble(2, 3, 0, MarbleColor::White),
+                marble(-4, 6, 1, MarbleColor::White),
+            ]
+        );
+
+        let partial = r#"(version: 2, tiles: [], marbles: [(color: "red", position: (2, 3))])"#;
+        assert_eq!(
+            Board::from_ron(partial).unwrap().marbles,
+            vec![marble(2, 3, 0, MarbleColor::Red)]
+        );
+
+        let unknown = r#"(version: 2, tiles: [], marbles: [(color: "plaid", position: (2, 3))])"#;
+        assert!(matches!(
+            Board::from_ron(unknown),
+            Err(BoardError::UnknownColor(_))
+        ));
+
+        let duplicate = "(version: 2, tiles: [], marbles: [(id: 1, position: (2, 3)), (1, 7)])";
+        assert!(matches!(
+            Board::from_ron(duplicate),
+            Err(BoardError::DuplicateMarbleId(1))
+        ));
+    }
+
+    #[test]
+    fn translate() {
+        let board = Board {
+            tiles: vec![
+                place(Tile::Path, 0, 0, false, false),
+                place(Tile::Shimmy, 3, 4, false, true),
+            ],
+            marbles: vec![marble(2, 3, 0, MarbleColor::Red)],
+        };
+        assert!(is_legal_shift(ivec2(-2, 8)));
+        assert!(!is_legal_shift(ivec2(1, 0)));
+        assert!(!is_legal_shift(ivec2(0, 2)));
+
+        let moved = board.translated(ivec2(-2, 8));
+        assert_eq!(moved.tiles[1].extent.origin(), GridPosition(ivec2(1, 12)));
+        assert!(moved.tiles[1].flip_y);
+        assert_eq!(moved.marbles[0].position, GridPosition(ivec2(0, 11)));
+        assert_eq!(moved.marbles_on(&moved.tiles[0]), moved.marbles);
+        assert!(moved.marbles_on(&moved.tiles[1]).is_empty());
+        // The moved board still passes the file format's placement checks.
+        assert_eq!(Board::from_ron(&moved.to_ron()).unwrap(), moved);
+        assert_eq!(moved.translated(ivec2(2, -8)), board);
+    }
+
+    #[test]
+    fn mirror() {
+        let board = Board {
+            tiles: vec![
+                place(Tile::Switch, 0, 0, false, false),
+                place(Tile::Shimmy, 9, 4, false, false),
+            ],
+            marbles: vec![marble(2, 3, 0, MarbleColor::Red)],
+        };
+
+        let mirrored = board.mirrored(true, false);
+        // The group spans x from 0 to 13, so mirroring shifts it by one unit
+        // to keep the shimmy on an odd column.
+        assert_eq!(mirrored.tiles[0].extent.origin(), GridPosition(ivec2(6, 0)));
+        assert_eq!(mirrored.tiles[1].extent.origin(), GridPosition(ivec2(1, 4)));
+        assert!(mirrored.tiles[0].flip_x);
+        assert_eq!(mirrored.marbles[0].position, GridPosition(ivec2(12, 3)));
+        // Marbles land where the flipped tile's sockets are.
+        let is_socket = |board: &Board| {
+            let switch = board.tiles[0];
+            switch
+                .outputs()
+                .chain(switch.sticky())
+                .any(|pos| pos == board.marbles[0].position)
+        };
+        assert!(is_socket(&board));
+        assert!(is_socket(&mirrored));
+        assert_eq!(Board::from_ron(&mirrored.to_ron()).unwrap(), mirrored);
+
+        let flipped = board.mirrored(false, true);
+        assert_eq!(flipped.tiles[0].extent.origin(), GridPosition(ivec2(0, 4)));
+        assert_eq!(flipped.tiles[1].extent.origin(), GridPosition(ivec2(9, 0)));
+        assert_eq!(flipped.marbles[0].position, GridPosition(ivec2(2, 5)));
+        assert_eq!(flipped.mirrored(false, true), board);
+    }
+
+    #[test]
+    fn bad_files() {
+        let future = "(version: 99, tiles: [])";
+        assert!(matches!(
+            Board::from_ron(future),
+            Err(BoardError::UnsupportedVersion(99))
+        ));
+
+        let unknown = r#"(version: 1, tiles: [(kind: "teleporter", origin: (0, 0))])"#;
+        assert!(matches!(
+            Board::from_ron(unknown),
+            Err(BoardError::UnknownTile(_))
+        ));
+
+        let misaligned = r#"(version: 1, tiles: [(kind: "shimmy", origin: (0, 0))])"#;
+        assert!(matches!(
+            Board::from_ron(misaligned),
+            Err(BoardError::IllegalPosition(Tile::Shimmy, _))
+        ));
+
+        let overlap = r#"(version: 1, tiles: [
+            (kind: "switch", origin: (0, 0)),
+            (kind: "path", origin: (4, 0)),
+        ])"#;
+        assert!(matches!(
+            Board::from_ron(overlap),
+            Err(BoardError::Overlap(Tile::Path, _))
+        ));
+    }
+}
@@ -0,0 +1,213 @@
+//! Panning and zooming the board view.
+//!
+//! Zoom levels are whole numbers of physical pixels per world pixel, so the
+//! pixel art stays crisp with nearest-neighbour sampling.
+
+use bevy::{input::mouse::AccumulatedMouseScroll, prelude::*, window::PrimaryWindow};
+
+use crate::{
+    MainCamera,
+    blueprint::BlueprintNamePrompt,
+    shortcuts::{Keymap, Shortcut},
+    tile::GridExtent,
+};
+
+/// How fast the keyboard pans the view, in logical pixels per second.
+const PAN_SPEED: f32 = 100.0;
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraZoom>()
+            .add_systems(
+                Update,
+                (
+                    camera_keyboard.run_if(not(resource_exists::<BlueprintNamePrompt>)),
+                    mouse_pan,
+                    mouse_zoom,
+                    apply_zoom,
+                )
+                    .chain(),
+            )
+            .add_observer(fit_to_view);
+    }
+}
+
+/// The number of physical pixels per world pixel.
+#[derive(Resource)]
+pub struct CameraZoom(pub i32);
+
+impl CameraZoom {
+    pub const MIN: i32 = 1;
+    pub const MAX: i32 = 16;
+
+    /// The orthographic projection scale for this zoom level.
+    fn projection_scale(&self, scale_factor: f32) -> f32 {
+        scale_factor / self.0 as f32
+    }
+}
+
+impl Default for CameraZoom {
+    /// Match the window's scale factor, so that the default projection is unchanged.
+    fn default() -> Self {
+        Self(4)
+    }
+}
+
+/// Zoom and centre the view so that the whole board is visible.
+#[derive(Event)]
+pub struct FitToView;
+
+/// Pan with the pan shortcuts, Shift+WASD by default.
+fn camera_keyboard(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    keymap: Res<Keymap>,
+    time: Res<Time>,
+    camera: Single<(&mut Transform, &Projection), With<MainCamera>>,
+) {
+    let mut direction = Vec2::ZERO;
+    if keymap.pressed(Shortcut::PanUp, &keyboard) {
+        direction.y += 1.0;
+    }
+    if keymap.pressed(Shortcut::PanDown, &keyboard) {
+        direction.y -= 1.0;
+    }
+    if keymap.pressed(Shortcut::PanLeft, &keyboard) {
+        direction.x -= 1.0;
+    }
+    if keymap.pressed(Shortcut::PanRight, &keyboard) {
+        direction.x += 1.0;
+    }
+    if direction == Vec2::ZERO {
+        return;
+    }
+    let (mut transform, projection) = camera.into_inner();
+    let distance = PAN_SPEED * time.delta_secs() * projection_scale(projection);
+    transform.translation += (direction.normalize() * distance).extend(0.0);
+}
+
+/// Pan by dragging with the middle mouse button.
+fn mouse_pan(
+    buttons: Res<ButtonInput<MouseButton>>,
+    mut cursor_moved: EventReader<CursorMoved>,
+    camera: Single<(&mut Transform, &Projection), With<MainCamera>>,
+) {
+    let (mut transform, projection) = camera.into_inner();
+    for event in cursor_moved.read() {
+        if !buttons.pressed(MouseButton::Middle) {
+            continue;
+        }
+        if let Some(delta) = event.delta {
+            // Window coordinates have y pointing down.
+            let delta = vec2(-delta.x, delta.y) * projection_scale(projection);
+            transform.translation += delta.extend(0.0);
+        }
+    }
+}
+
+/// Zoom with the mouse wheel, keeping the point under the cursor fixed.
+fn mouse_zoom(
+    scroll: Res<AccumulatedMouseScroll>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform, &mut Transform), With<MainCamera>>,
+    mut zoom: ResMut<CameraZoom>,
+) {
+    // Zoom one level per frame of scrolling, whatever the scroll unit.
+    let scrolled = scroll.delta.y;
+    if scrolled == 0.0 {
+        return;
+    }
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+    let (camera, camera_transform, mut transform) = camera.into_inner();
+    if !camera
+        .logical_viewport_rect()
+        .is_some_and(|rect| rect.contains(cursor))
+    {
+        return;
+    }
+    let Ok(anchor) = camera.viewport_to_world_2d(camera_transform, cursor) else {
+        return;
+    };
+
+    let level = (zoom.0 + scrolled.signum() as i32).clamp(CameraZoom::MIN, CameraZoom::MAX);
+    if level == zoom.0 {
+        return;
+    }
+    // Scale the camera's offset from the anchor by the change in projection scale.
+    let ratio = zoom.0 as f32 / level as f32;
+    let center = transform.translation.truncate();
+    let center = anchor + (center - anchor) * ratio;
+    transform.translation = center.extend(transform.translation.z);
+    zoom.0 = level;
+}
+
+/// Update the camera projection when the zoom level changes.
+fn apply_zoom(
+    zoom: Res<CameraZoom>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    mut projection: Single<&mut Projection, With<MainCamera>>,
+) {
+    if !zoom.is_changed() {
+        return;
+    }
+    if let Projection::Orthographic(ortho) = projection.as_mut() {
+        ortho.scale = zoom.projection_scale(window.scale_factor());
+    }
+}
+
+fn fit_to_view(
+    _trigger: Trigger<FitToView>,
+    tiles: Query<&GridExtent>,
+    camera: Single<(&Camera, &mut Transform), With<MainCamera>>,
+    mut zoom: ResMut<CameraZoom>,
+) {
+    let Some(bounds) = tiles
+        .iter()
+        .map(GridExtent::world_rect)
+        .reduce(|a, b| a.union(b))
+    else {
+        return;
+    };
+    let (camera, mut transform) = camera.into_inner();
+    let Some(viewport) = camera.physical_viewport_size() else {
+        return;
+    };
+    zoom.0 = fit_zoom(bounds, viewport);
+    transform.translation = bounds.center().extend(transform.translation.z);
+}
+
+/// The largest zoom level that fits `bounds` inside a viewport of the given physical size.
+fn fit_zoom(bounds: Rect, viewport: UVec2) -> i32 {
+    let fit = viewport.as_vec2() / bounds.size();
+    (fit.min_element().floor() as i32).clamp(CameraZoom::MIN, CameraZoom::MAX)
+}
+
+/// The number of world pixels per logical pixel.
+fn projection_scale(projection: &Projection) -> f32 {
+    match projection {
+        Projection::Orthographic(ortho) => ortho.scale,
+        _ => 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_zoom_levels() {
+        let viewport = uvec2(800, 688);
+        // A single 16 pixel tile fits at the maximum zoom.
+        let tile = Rect::new(0.0, 0.0, 16.0, 16.0);
+        assert_eq!(fit_zoom(tile, viewport), CameraZoom::MAX);
+        // The limiting dimension is the width: 800 / 200 = 4.
+        let board = Rect::new(-100.0, -80.0, 100.0, 80.0);
+        assert_eq!(fit_zoom(board, viewport), 4);
+        // A huge board is clamped to the minimum zoom.
+        let huge = Rect::new(0.0, 0.0, 10_000.0, 10.0);
+        assert_eq!(fit_zoom(huge, viewport), CameraZoom::MIN);
+    }
+}
@@ -0,0 +1,353 @@
+//! Editing and running boards with a gamepad.
+//!
+//! The D-pad or left stick moves the grid cursor. South (A on an Xbox pad)
+//! clicks at the cursor, East deletes the tile there, West flips the ghost
+//! tile or the tile under the cursor, and North picks the next kind of tile.
+//! The shoulder buttons switch between placing tiles, placing marbles and
+//! deleting, and Start plays or pauses. As with the mouse, placed tiles can
+//! only be deleted when idle or deleting, and only flipped when idle.
+//!
+//! Gamepad state is read from Bevy's `Gamepad` components and turned into
+//! `PadCommand` events, so the mapping can be tested with synthetic input.
+
+use bevy::{platform::collections::HashMap, prelude::*};
+
+use crate::{
+    SimState,
+    blueprint::BlueprintNamePrompt,
+    place_tile::{DeleteTileAt, FlipTileAt, GhostTile},
+    shortcuts::{CursorInput, GridCursor, move_cursor},
+    tile::{ALL_TILES, Tile},
+    ui::{Action, RunAction, UiTileSelected},
+};
+
+/// How far the stick must be pushed along an axis to move the cursor.
+const STICK_THRESHOLD: f32 = 0.5;
+
+/// How long the stick must be held before the cursor starts repeating, in seconds.
+const STICK_REPEAT_DELAY: f32 = 0.4;
+
+/// How often the cursor repeats while the stick is held, in seconds.
+const STICK_REPEAT_INTERVAL: f32 = 0.15;
+
+/// The modes that the shoulder buttons switch between, in order.
+const EDIT_MODES: [SimState; 3] = [
+    SimState::Placing,
+    SimState::PlacingMarbles,
+    SimState::Deleting,
+];
+
+pub struct ControllerPlugin;
+
+impl Plugin for ControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PadCommand>().add_systems(
+            Update,
+            (read_gamepads, apply_pad_commands)
+                .chain()
+                .before(move_cursor)
+                .run_if(not(resource_exists::<BlueprintNamePrompt>)),
+        );
+    }
+}
+
+/// Something a gamepad asks the editor to do.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PadCommand {
+    /// Move the grid cursor by this many cells.
+    Move(IVec2),
+    /// Click at the grid cursor.
+    Click,
+    /// Delete the tile under the grid cursor.
+    Delete,
+    /// Flip the ghost tile, or the tile under the grid cursor.
+    Flip,
+    /// Pick the next kind of tile to place.
+    NextTile,
+    NextMode,
+    PreviousMode,
+    PlayPause,
+}
+
+/// The buttons that map directly to a command.
+const BUTTONS: &[(GamepadButton, PadCommand)] = &[
+    (GamepadButton::DPadUp, PadCommand::Move(IVec2::Y)),
+    (GamepadButton::DPadDown, PadCommand::Move(IVec2::NEG_Y)),
+    (GamepadButton::DPadLeft, PadCommand::Move(IVec2::NEG_X)),
+    (GamepadButton::DPadRight, PadCommand::Move(IVec2::X)),
+    (GamepadButton::South, PadCommand::Click),
+    (GamepadButton::East, PadCommand::Delete),
+    (GamepadButton::West, PadCommand::Flip),
+    (GamepadButton::North, PadCommand::NextTile),
+    (GamepadButton::LeftTrigger, PadCommand::PreviousMode),
+    (GamepadButton::RightTrigger, PadCommand::NextMode),
+    (GamepadButton::Start, PadCommand::PlayPause),
+];
+
+/// Which way a stick is pushed, one cell at most along each axis.
+fn stick_direction(stick: Vec2) -> IVec2 {
+    let axis = |value: f32| {
+        if value >= STICK_THRESHOLD {
+            1
+        } else if value <= -STICK_THRESHOLD {
+            -1
+        } else {
+            0
+        }
+    };
+    IVec2::new(axis(stick.x), axis(stick.y))
+}
+
+/// A held stick, repeating its move while it stays pushed the same way.
+struct StickRepeat {
+    direction: IVec2,
+    /// Seconds until the next repeat.
+    countdown: f32,
+}
+
+/// Turn each gamepad's buttons and stick into commands.
+fn read_gamepads(
+    gamepads: Query<(Entity, &Gamepad)>,
+    time: Res<Time>,
+    mut sticks: Local<HashMap<Entity, StickRepeat>>,
+    mut commands: EventWriter<PadCommand>,
+) {
+    for (entity, gamepad) in &gamepads {
+        for &(button, command) in BUTTONS {
+            if gamepad.just_pressed(button) {
+                commands.write(command);
+            }
+        }
+
+        let direction = stick_direction(gamepad.left_stick());
+        if direction == IVec2::ZERO {
+            sticks.remove(&entity);
+            continue;
+        }
+        match sticks.get_mut(&entity) {
+            Some(held) if held.direction == direction => {
+                held.countdown -= time.delta_secs();
+                if held.countdown <= 0.0 {
+                    held.countdown += STICK_REPEAT_INTERVAL;
+                    commands.write(PadCommand::Move(direction));
+                }
+            }
+            _ => {
+                sticks.insert(
+                    entity,
+                    StickRepeat {
+                        direction,
+                        countdown: STICK_REPEAT_DELAY,
+                    },
+                );
+                commands.write(PadCommand::Move(direction));
+            }
+        }
+    }
+}
+
+/// The edit mode a shoulder button switches to.
+fn switch_mode(current: SimState, forward: bool) -> SimState {
+    let count = EDIT_MODES.len();
+    let index = match EDIT_MODES.iter().position(|&mode| mode == current) {
+        Some(index) if forward => (index + 1) % count,
+        Some(index) => (index + count - 1) % count,
+        None if forward => 0,
+        None => count - 1,
+    };
+    EDIT_MODES[index]
+}
+
+fn apply_pad_commands(
+    mut pad_commands: EventReader<PadCommand>,
+    mut cursor_input: EventWriter<CursorInput>,
+    cursor: Res<GridCursor>,
+    state: Res<State<SimState>>,
+    mut next_state: ResMut<NextState<SimState>>,
+    mut ghost: Query<(&mut Sprite, &Tile), With<GhostTile>>,
+    mut commands: Commands,
+) {
+    for &command in pad_commands.read() {
+        match command {
+            PadCommand::Move(cells) => {
+                cursor_input.write(CursorInput::Move(cells));
+            }
+            PadCommand::Click => {
+                cursor_input.write(CursorInput::Click);
+            }
+            PadCommand::Delete => {
+                if matches!(state.get(), SimState::Idle | SimState::Deleting)
+                    && let Some(point) = cursor.point()
+                {
+                    commands.trigger(DeleteTileAt(point));
+                }
+            }
+            PadCommand::Flip => {
+                if *state.get() == SimState::Placing
+                    && let Ok((mut sprite, _)) = ghost.single_mut()
+                {
+                    sprite.flip_x = !sprite.flip_x;
+                } else if *state.get() == SimState::Idle
+                    && let Some(point) = cursor.point()
+                {
+                    commands.trigger(FlipTileAt {
+                        world_pos: point,
+                        flip_x: true,
+                        flip_y: false,
+                    });
+                }
+            }
+            PadCommand::NextTile => {
+                let tile = match ghost.single() {
+                    Ok((_, tile)) => tile.next(),
+                    Err(_) => ALL_TILES[0],
+                };
+                commands.trigger(UiTileSelected(tile));
+            }
+            PadCommand::NextMode | PadCommand::PreviousMode => {
+                let mode = switch_mode(*state.get(), command == PadCommand::NextMode);
+                if mode == SimState::Placing {
+                    // Placing needs a ghost tile; keep the last kind picked.
+                    let tile = ghost.single().map_or(ALL_TILES[0], |(_, &tile)| tile);
+                    commands.trigger(UiTileSelected(tile));
+                } else {
+                    next_state.set(mode);
+                }
+            }
+            PadCommand::PlayPause => {
+                let action = if *state.get() == SimState::Running {
+                    Action::Pause
+                } else {
+                    Action::Play
+                };
+                commands.trigger(RunAction(action));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        input::{
+            InputPlugin,
+            gamepad::{
+                GamepadConnection, GamepadConnectionEvent, RawGamepadAxisChangedEvent,
+                RawGamepadButtonChangedEvent, RawGamepadEvent,
+            },
+        },
+        state::app::StatesPlugin,
+    };
+
+    use super::*;
+    use crate::grid::GridPosition;
+
+    /// An app that only turns gamepad input into commands.
+    fn pad_app() -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugins(InputPlugin)
+            .init_resource::<Time>()
+            .add_event::<PadCommand>()
+            .add_systems(Update, read_gamepads);
+        let gamepad = app.world_mut().spawn_empty().id();
+        app.world_mut().send_event(GamepadConnectionEvent::new(
+            gamepad,
+            GamepadConnection::Connected {
+                name: "Test gamepad".into(),
+                vendor_id: None,
+                product_id: None,
+            },
+        ));
+        app.update();
+        (app, gamepad)
+    }
+
+    fn send(app: &mut App, event: RawGamepadEvent) -> Vec<PadCommand> {
+        app.world_mut().send_event(event);
+        app.update();
+        app.world_mut()
+            .resource_mut::<Events<PadCommand>>()
+            .drain()
+            .collect()
+    }
+
+    fn button(gamepad: Entity, button: GamepadButton, value: f32) -> RawGamepadEvent {
+        RawGamepadEvent::Button(RawGamepadButtonChangedEvent::new(gamepad, button, value))
+    }
+
+    #[test]
+    fn buttons_become_commands() {
+        let (mut app, gamepad) = pad_app();
+        for &(pad_button, command) in BUTTONS {
+            assert_eq!(
+                send(&mut app, button(gamepad, pad_button, 1.0)),
+                [command],
+                "{pad_button:?}"
+            );
+            // Holding a button doesn't repeat it.
+            assert_eq!(send(&mut app, button(gamepad, pad_button, 1.0)), []);
+            assert_eq!(send(&mut app, button(gamepad, pad_button, 0.0)), []);
+        }
+    }
+
+    #[test]
+    fn stick_moves_once_until_repeat() {
+        let (mut app, gamepad) = pad_app();
+        let axis = |axis, value| {
+            RawGamepadEvent::Axis(RawGamepadAxisChangedEvent::new(gamepad, axis, value))
+        };
+        assert_eq!(
+            send(&mut app, axis(GamepadAxis::LeftStickX, -0.9)),
+            [PadCommand::Move(IVec2::NEG_X)]
+        );
+        // No time passes in the test, so the stick doesn't repeat.
+        assert_eq!(send(&mut app, axis(GamepadAxis::LeftStickX, -0.95)), []);
+        assert_eq!(
+            send(&mut app, axis(GamepadAxis::LeftStickY, 0.9)),
+            [PadCommand::Move(IVec2::new(-1, 1))]
+        );
+        assert_eq!(stick_direction(vec2(0.3, -0.2)), IVec2::ZERO);
+    }
+
+    /// Count the tiles a gamepad asks to delete or flip, in a given state.
+    fn edits_in(state: SimState, command: PadCommand) -> usize {
+        #[derive(Resource, Default)]
+        struct Edits(usize);
+
+        let mut app = App::new();
+        app.add_plugins(StatesPlugin)
+            .insert_state(state)
+            .insert_resource(GridCursor(Some(GridPosition(IVec2::ZERO))))
+            .init_resource::<Edits>()
+            .add_event::<PadCommand>()
+            .add_event::<CursorInput>()
+            .add_observer(|_: Trigger<DeleteTileAt>, mut edits: ResMut<Edits>| edits.0 += 1)
+            .add_observer(|_: Trigger<FlipTileAt>, mut edits: ResMut<Edits>| edits.0 += 1)
+            .add_systems(Update, apply_pad_commands);
+        app.world_mut().send_event(command);
+        app.update();
+        app.world().resource::<Edits>().0
+    }
+
+    #[test]
+    fn edits_only_when_editing() {
+        assert_eq!(edits_in(SimState::Running, PadCommand::Delete), 0);
+        assert_eq!(edits_in(SimState::Paused, PadCommand::Delete), 0);
+        assert_eq!(edits_in(SimState::Deleting, PadCommand::Delete), 1);
+        assert_eq!(edits_in(SimState::Idle, PadCommand::Delete), 1);
+        assert_eq!(edits_in(SimState::Running, PadCommand::Flip), 0);
+        assert_eq!(edits_in(SimState::Idle, PadCommand::Flip), 1);
+    }
+
+    #[test]
+    fn shoulders_cycle_modes() {
+        assert_eq!(switch_mode(SimState::Idle, true), SimState::Placing);
+        assert_eq!(switch_mode(SimState::Idle, false), SimState::Deleting);
+        assert_eq!(
+            switch_mode(SimState::Placing, true),
+            SimState::PlacingMarbles
+        );
+        assert_eq!(switch_mode(SimState::Deleting, true), SimState::Placing);
+        assert_eq!(switch_mode(SimState::Placing, false), SimState::Deleting);
+    }
+}
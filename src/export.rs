@@ -0,0 +1,397 @@
+//! Pictures of whole boards, for pasting into documents.
+//!
+//! `render_png` draws the tile and marble sprites onto an image, scaled up by
+//! a whole number so the pixel art stays crisp. `render_svg` draws the same
+//! board as vector shapes: each tile's outline and name, its inputs and
+//! outputs (which show how it is flipped), and the marbles.
+//!
+//! Both use world pixels, with a small margin around the board.
+
+use std::{collections::HashMap, fmt::Display, fmt::Write, io::Cursor, path::Path};
+
+use bevy::{
+    color::{ColorToPacked, Srgba},
+    math::{IVec2, UVec2},
+};
+use image::{ImageFormat, Rgba, RgbaImage, imageops};
+
+use crate::{
+    board::Board,
+    grid::{GRID_UNITS_PER_TILE, GridPosition, PIXELS_PER_GRID_UNIT},
+    tile::{Marble, Tile},
+};
+
+/// The space left around the board, in world pixels.
+const MARGIN: i32 = PIXELS_PER_GRID_UNIT;
+
+/// The radius of a marble, in world pixels.
+const MARBLE_RADIUS: i32 = 4;
+
+/// The background colour, matching the editor's.
+const BACKGROUND: Srgba = Srgba::rgb(0.3, 0.3, 0.3);
+
+/// The colour of tiles in SVG drawings.
+const SVG_TILE_FILL: &str = "#d8cfb8";
+
+/// The sprites used to draw boards.
+pub struct Sprites {
+    tiles: HashMap<Tile, RgbaImage>,
+    marble: RgbaImage,
+}
+
+impl Sprites {
+    pub fn new(marble: RgbaImage) -> Self {
+        Self {
+            tiles: HashMap::new(),
+            marble,
+        }
+    }
+
+    pub fn insert_tile(&mut self, tile: Tile, image: RgbaImage) {
+        self.tiles.insert(tile, image);
+    }
+
+    /// Load the sprites for some tiles, and the marble sprite, from an asset directory.
+    pub fn load(dir: &Path, tiles: impl IntoIterator<Item = Tile>) -> Result<Self, ExportError> {
+        let load = |filename: &str| {
+            image::open(dir.join(filename))
+                .map(|image| image.into_rgba8())
+                .map_err(ExportError::Image)
+        };
+        let mut sprites = Self::new(load(Marble::sprite_filename())?);
+        for tile in tiles {
+            sprites.insert_tile(tile, load(&tile.sprite_filename())?);
+        }
+        Ok(sprites)
+    }
+}
+
+#[cfg(test)]
+impl Sprites {
+    /// Load every tile's sprite from the repository's assets, for tests.
+    pub(crate) fn repo_assets() -> Self {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+        Self::load(&dir, crate::tile::ALL_TILES.iter().copied()).unwrap()
+    }
+}
+
+/// An area to draw, in world pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Bounds {
+    min: IVec2,
+    max: IVec2,
+}
+
+impl Bounds {
+    /// The area covering the tiles and marbles of some boards, plus a margin.
+    pub(crate) fn covering<'a>(
+        boards: impl IntoIterator<Item = &'a Board>,
+    ) -> Result<Self, ExportError> {
+        let mut corners = boards.into_iter().flat_map(|board| {
+            let tile_corners = board.tiles.iter().flat_map(|sim_tile| {
+                let rect = sim_tile.extent.world_rect();
+                [rect.min.as_ivec2(), rect.max.as_ivec2()]
+            });
+            let marble_corners = board.marbles.iter().flat_map(|placed| {
+                let center = placed.position.to_world().as_ivec2();
+                [center - MARBLE_RADIUS, center + MARBLE_RADIUS]
+            });
+            tile_corners.chain(marble_corners)
+        });
+        let first = corners.next().ok_or(ExportError::EmptyBoard)?;
+        let (min, max) = corners.fold((first, first), |(min, max), corner| {
+            (min.min(corner), max.max(corner))
+        });
+        Ok(Self {
+            min: min - MARGIN,
+            max: max + MARGIN,
+        })
+    }
+
+    fn size(&self) -> IVec2 {
+        self.max - self.min
+    }
+
+    /// The size of a picture of this area, scaled up by a whole number.
+    pub(crate) fn picture_size(&self, scale: u32) -> UVec2 {
+        self.size().as_uvec2() * scale
+    }
+
+    /// Where a world position is in the picture.
+    ///
+    /// Picture rows run down the picture, while world y runs up it.
+    fn to_picture(self, world: IVec2) -> IVec2 {
+        IVec2::new(world.x - self.min.x, self.max.y - world.y)
+    }
+}
+
+/// Blend a pixel over another.
+fn blend(under: Rgba<u8>, over: Rgba<u8>) -> Rgba<u8> {
+    let alpha = u32::from(over[3]);
+    let mix = |a: u8, b: u8| ((u32::from(a) * (255 - alpha) + u32::from(b) * alpha) / 255) as u8;
+    Rgba([
+        mix(under[0], over[0]),
+        mix(under[1], over[1]),
+        mix(under[2], over[2]),
+        under[3].max(over[3]),
+    ])
+}
+
+/// Draw a sprite with its top left corner at a pixel of the unscaled picture.
+fn draw_sprite(canvas: &mut RgbaImage, sprite: &RgbaImage, top_left: IVec2, scale: u32) {
+    for (x, y, &pixel) in sprite.enumerate_pixels() {
+        if pixel[3] == 0 {
+            continue;
+        }
+        let corner = (top_left + IVec2::new(x as i32, y as i32)).as_uvec2() * scale;
+        for dy in 0..scale {
+            for dx in 0..scale {
+                let under = canvas.get_pixel_mut(corner.x + dx, corner.y + dy);
+                *under = blend(*under, pixel);
+            }
+        }
+    }
+}
+
+/// Draw a board with its sprites, scaled up by a whole number.
+pub fn render_png(board: &Board, sprites: &Sprites, scale: u32) -> Result<RgbaImage, ExportError> {
+    if scale == 0 {
+        return Err(ExportError::ZeroScale);
+    }
+    draw_board(board, sprites, scale, Bounds::covering([board])?)
+}
+
+/// Draw the part of a board within some bounds.
+pub(crate) fn draw_board(
+    board: &Board,
+    sprites: &Sprites,
+    scale: u32,
+    bounds: Bounds,
+) -> Result<RgbaImage, ExportError> {
+    let size = bounds.picture_size(scale);
+    let background = Rgba(BACKGROUND.to_u8_array());
+    let mut canvas = RgbaImage::from_pixel(size.x, size.y, background);
+    let mut draw = |sprite: &RgbaImage, top_left: IVec2| {
+        draw_sprite(&mut canvas, sprite, bounds.to_picture(top_left), scale);
+    };
+
+    let indicator = RgbaImage::from_pixel(2, 2, Rgba([255; 4]));
+    for sim_tile in &board.tiles {
+        let sprite = sprites
+            .tiles
+            .get(&sim_tile.tile)
+            .ok_or(ExportError::MissingSprite(sim_tile.tile))?;
+        let mut sprite = sprite.clone();
+        if sim_tile.flip_x {
+            imageops::flip_horizontal_in_place(&mut sprite);
+        }
+        if sim_tile.flip_y {
+            imageops::flip_vertical_in_place(&mut sprite);
+        }
+        let rect = sim_tile.extent.world_rect();
+        draw(&sprite, IVec2::new(rect.min.x as i32, rect.max.y as i32));
+
+        // The editor marks the tile's state with a small white square.
+        if let Some(io) = sim_tile.tile.state_indicator(sim_tile.state) {
+            let center = sim_tile.io_to_grid(io).to_world().as_ivec2();
+            draw(&indicator, center + IVec2::new(-1, 1));
+        }
+    }
+
+    for placed in &board.marbles {
+        let tint = placed.marble.color.tint().to_srgba().to_u8_array();
+        let mut sprite = sprites.marble.clone();
+        for pixel in sprite.pixels_mut() {
+            for channel in 0..3 {
+                pixel[channel] = (u32::from(pixel[channel]) * u32::from(tint[channel]) / 255) as u8;
+            }
+        }
+        let center = placed.position.to_world().as_ivec2();
+        let half_size = IVec2::new(sprite.width() as i32, sprite.height() as i32) / 2;
+        draw(&sprite, center + IVec2::new(-half_size.x, half_size.y));
+    }
+    Ok(canvas)
+}
+
+/// Encode a picture as a PNG file.
+pub fn encode_png(image: &RgbaImage) -> Result<Vec<u8>, ExportError> {
+    let mut bytes = Cursor::new(Vec::new());
+    image
+        .write_to(&mut bytes, ImageFormat::Png)
+        .map_err(ExportError::Image)?;
+    Ok(bytes.into_inner())
+}
+
+/// A colour in SVG's `#rrggbb` notation.
+fn svg_color(color: Srgba) -> String {
+    let [r, g, b, _] = color.to_u8_array();
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// Draw a board as an SVG document, `scale` times its size in world pixels.
+pub fn render_svg(board: &Board, scale: u32) -> Result<String, ExportError> {
+    if scale == 0 {
+        return Err(ExportError::ZeroScale);
+    }
+    let bounds = Bounds::covering([board])?;
+    let size = bounds.size();
+    let point = |position: GridPosition| {
+        let picture = bounds.to_picture(position.to_world().as_ivec2());
+        (picture.x, picture.y)
+    };
+    let unit = PIXELS_PER_GRID_UNIT;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        size.x * scale as i32,
+        size.y * scale as i32,
+        size.x,
+        size.y
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect width="{}" height="{}" fill="{}"/>"#,
+        size.x,
+        size.y,
+        svg_color(BACKGROUND)
+    );
+
+    for sim_tile in &board.tiles {
+        let extent = sim_tile.extent;
+        let (x, top) = point(GridPosition(
+            extent.origin().0 + IVec2::new(0, GRID_UNITS_PER_TILE),
+        ));
+        let width = extent.width() * unit;
+        let height = GRID_UNITS_PER_TILE * unit;
+        let flips = match (sim_tile.flip_x, sim_tile.flip_y) {
+            (false, false) => "",
+            (true, false) => " \u{2194}",
+            (false, true) => " \u{2195}",
+            (true, true) => " \u{2194}\u{2195}",
+        };
+        let _ = writeln!(svg, r#"<g class="tile {}">"#, sim_tile.tile.name());
+        let _ = writeln!(
+            svg,
+            r#"  <rect x="{x}" y="{top}" width="{width}" height="{height}" fill="{SVG_TILE_FILL}" stroke="black" stroke-width="0.5"/>"#
+        );
+        let _ = writeln!(
+            svg,
+            r#"  <text x="{}" y="{}" font-family="sans-serif" font-size="3" text-anchor="middle">{}{flips}</text>"#,
+            x as f32 + width as f32 / 2.0,
+            top as f32 + height as f32 / 2.0 + 1.0,
+            sim_tile.tile.name()
+        );
+        // Inputs are hollow, and outputs solid, so the flips can be seen.
+        let ios = [
+            (sim_tile.tile.inputs(), "none"),
+            (sim_tile.tile.outputs(), "black"),
+        ];
+        for (coords, fill) in ios {
+            for coord in coords {
+                let (cx, cy) = point(coord.to_grid(extent, sim_tile.flip_x, sim_tile.flip_y));
+                let _ = writeln!(
+                    svg,
+                    r#"  <circle cx="{cx}" cy="{cy}" r="1" fill="{fill}" stroke="black" stroke-width="0.3"/>"#
+                );
+            }
+        }
+        let _ = writeln!(svg, "</g>");
+    }
+
+    for placed in &board.marbles {
+        let (cx, cy) = point(placed.position);
+        let _ = writeln!(
+            svg,
+            r#"<g class="marble"><circle cx="{cx}" cy="{cy}" r="{}" fill="{}" stroke="black" stroke-width="0.5"/><text x="{cx}" y="{}" font-family="sans-serif" font-size="3" text-anchor="middle">{}</text></g>"#,
+            MARBLE_RADIUS - 1,
+            svg_color(placed.marble.color.tint().to_srgba()),
+            cy + 1,
+            placed.marble.id
+        );
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+/// An error drawing a board.
+#[derive(Debug)]
+pub enum ExportError {
+    EmptyBoard,
+    ZeroScale,
+    /// Animations can show from 1 to 1000 ticks a second.
+    TickRate(u32),
+    MissingSprite(Tile),
+    Image(image::ImageError),
+    Png(png::EncodingError),
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::EmptyBoard => write!(f, "the board is empty"),
+            ExportError::ZeroScale => write!(f, "the scale must be at least 1"),
+            ExportError::TickRate(rate) => {
+                write!(f, "can't show {rate} ticks a second; pick from 1 to 1000")
+            }
+            ExportError::MissingSprite(tile) => write!(f, "no sprite for {}", tile.name()),
+            ExportError::Image(e) => write!(f, "image error: {e}"),
+            ExportError::Png(e) => write!(f, "PNG error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_board() -> Board {
+        Board::from_ron(
+            r#"(
+                version: 3,
+                tiles: [
+                    (kind: "path", origin: (0, 0)),
+                    (kind: "switch", origin: (4, -4), flip_x: true),
+                ],
+                marbles: [(id: 0, color: "red", position: (2, 3))],
+            )"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn png_covers_the_board() {
+        let board = sample_board();
+        // The tiles span x 0..48 and y -16..16, plus the margin.
+        let image = render_png(&board, &Sprites::repo_assets(), 3).unwrap();
+        assert_eq!(image.dimensions(), (56 * 3, 40 * 3));
+        // The corners are background.
+        let background = Rgba(BACKGROUND.to_u8_array());
+        assert_eq!(*image.get_pixel(0, 0), background);
+        let png = encode_png(&image).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+
+        assert!(matches!(
+            render_png(&Board::default(), &Sprites::repo_assets(), 1),
+            Err(ExportError::EmptyBoard)
+        ));
+        assert!(matches!(
+            render_png(&board, &Sprites::new(RgbaImage::new(8, 8)), 1),
+            Err(ExportError::MissingSprite(Tile::Path))
+        ));
+    }
+
+    #[test]
+    fn svg_shows_tiles_and_marbles() {
+        let svg = render_svg(&sample_board(), 2).unwrap();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.contains(r#"width="112" height="80" viewBox="0 0 56 40""#));
+        assert!(svg.contains(r#"<g class="tile switch">"#));
+        assert!(svg.contains("switch \u{2194}</text>"));
+        assert_eq!(svg.matches(r#"class="marble""#).count(), 1);
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+}
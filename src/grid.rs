@@ -23,7 +23,7 @@ use crate::tile::Offset;
 pub const GRID_UNITS_PER_TILE: i32 = 4;
 pub const PIXELS_PER_GRID_UNIT: i32 = 4;
 
-#[derive(Clone, Copy, Debug, PartialEq, Component)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Component)]
 pub struct GridPosition(pub IVec2);
 
 impl GridPosition {
@@ -0,0 +1,134 @@
+//! A faint grid drawn over the board, and a readout of the grid position under the cursor.
+//!
+//! Horizontal lines mark the tile rows. Vertical lines mark every second grid
+//! unit, where most tiles and all marble entrances and exits sit, and fainter
+//! lines mark the odd grid units used by `Offset::Odd` tiles. The grid can be
+//! shown or hidden with a shortcut, G by default.
+
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::{
+    MainCamera,
+    grid::{GRID_UNITS_PER_TILE, GridPosition, PIXELS_PER_GRID_UNIT},
+    select::cursor_world_pos,
+    ui::UiGridReadout,
+};
+
+/// The colour of the lines between tile rows.
+const ROW_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.2);
+
+/// The colour of the lines at even grid units.
+const HALF_TILE_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.1);
+
+/// The colour of the lines at odd grid units.
+const QUARTER_TILE_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.04);
+
+/// The most lines drawn in either direction; finer lines are dropped when zoomed out.
+const MAX_LINES: usize = 400;
+
+pub struct GridOverlayPlugin;
+
+impl Plugin for GridOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GridOverlay>()
+            .add_observer(toggle_grid)
+            .add_systems(Update, (draw_grid, show_hovered_position));
+    }
+}
+
+/// Whether the background grid is drawn.
+#[derive(Resource)]
+pub struct GridOverlay {
+    pub visible: bool,
+}
+
+impl Default for GridOverlay {
+    fn default() -> Self {
+        Self { visible: true }
+    }
+}
+
+/// Show or hide the background grid.
+#[derive(Event)]
+pub struct ToggleGrid;
+
+fn toggle_grid(_trigger: Trigger<ToggleGrid>, mut overlay: ResMut<GridOverlay>) {
+    overlay.visible = !overlay.visible;
+}
+
+/// The multiples of `step` grid units between `min` and `max` world pixels,
+/// in world pixels, skipping any that are also multiples of `skip` units.
+fn grid_lines(min: f32, max: f32, step: i32, skip: Option<i32>) -> impl Iterator<Item = f32> {
+    let pixels = (step * PIXELS_PER_GRID_UNIT) as f32;
+    let first = (min / pixels).ceil() as i32;
+    let last = (max / pixels).floor() as i32;
+    (first..=last)
+        .map(move |n| n * step)
+        .filter(move |&units| skip.is_none_or(|skip| units % skip != 0))
+        .map(|units| (units * PIXELS_PER_GRID_UNIT) as f32)
+}
+
+fn draw_grid(
+    overlay: Res<GridOverlay>,
+    camera: Single<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut gizmos: Gizmos,
+) {
+    if !overlay.visible {
+        return;
+    }
+    let (camera, camera_transform) = *camera;
+    let Some(viewport) = camera.logical_viewport_rect() else {
+        return;
+    };
+    let (Ok(a), Ok(b)) = (
+        camera.viewport_to_world_2d(camera_transform, viewport.min),
+        camera.viewport_to_world_2d(camera_transform, viewport.max),
+    ) else {
+        return;
+    };
+    let view = Rect::from_corners(a, b);
+
+    let columns = [(1, Some(2), QUARTER_TILE_COLOR), (2, None, HALF_TILE_COLOR)];
+    for (step, skip, color) in columns {
+        if view.width() / (step * PIXELS_PER_GRID_UNIT) as f32 > MAX_LINES as f32 {
+            continue;
+        }
+        for x in grid_lines(view.min.x, view.max.x, step, skip) {
+            gizmos.line_2d(vec2(x, view.min.y), vec2(x, view.max.y), color);
+        }
+    }
+    for y in grid_lines(view.min.y, view.max.y, GRID_UNITS_PER_TILE, None).take(MAX_LINES) {
+        gizmos.line_2d(vec2(view.min.x, y), vec2(view.max.x, y), ROW_COLOR);
+    }
+}
+
+/// Show the grid position nearest the cursor in the UI panel.
+fn show_hovered_position(
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut text: Query<&mut Text, With<UiGridReadout>>,
+) {
+    let (camera, camera_transform) = *camera;
+    let caption = cursor_world_pos(&window, camera, camera_transform)
+        .map(|pos| GridPosition::from_world(pos).to_string())
+        .unwrap_or_default();
+    for mut text in &mut text {
+        if text.0 != caption {
+            text.0 = caption.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_cover_the_view() {
+        let rows: Vec<f32> = grid_lines(-20.0, 40.0, GRID_UNITS_PER_TILE, None).collect();
+        assert_eq!(rows, [-16.0, 0.0, 16.0, 32.0]);
+        // Odd columns leave out the even ones.
+        let odd: Vec<f32> = grid_lines(0.0, 20.0, 1, Some(2)).collect();
+        assert_eq!(odd, [4.0, 12.0, 20.0]);
+    }
+}
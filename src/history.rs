@@ -0,0 +1,358 @@
+//! Undo and redo for board edits.
+//!
+//! Every change to the board goes through an [`Edit`], which knows how to
+//! apply itself and how to build its inverse. Tiles are identified by their
+//! origin and marbles by their position, because undoing a deletion spawns
+//! new entities.
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    SimState,
+    board::PlacedMarble,
+    grid::{GRID_UNITS_PER_TILE, GridPosition},
+    place_marble::spawn_marble,
+    place_tile::spawn_tile,
+    sim::SimTile,
+    tile::{GridExtent, Marble, Tile, TileState},
+};
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<History>()
+            .add_systems(
+                Update,
+                undo_keyboard.run_if(not(
+                    in_state(SimState::Running).or(in_state(SimState::Paused))
+                )),
+            )
+            .add_observer(edit_board)
+            .add_observer(undo)
+            .add_observer(redo);
+    }
+}
+
+/// A reversible change to the board.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit {
+    /// Place a tile, along with marbles sitting on it.
+    PlaceTile {
+        tile: SimTile,
+        marbles: Vec<PlacedMarble>,
+    },
+    /// Delete a tile, along with marbles sitting on it.
+    DeleteTile {
+        tile: SimTile,
+        marbles: Vec<PlacedMarble>,
+    },
+    /// Flip the tile at `origin`, mirroring any marbles sitting on it.
+    FlipTile {
+        origin: GridPosition,
+        flip_x: bool,
+        flip_y: bool,
+    },
+    /// Change the internal state of the tile at `origin`.
+    SetTileState {
+        origin: GridPosition,
+        from: TileState,
+        to: TileState,
+    },
+    PlaceMarble(PlacedMarble),
+    RemoveMarble(PlacedMarble),
+    /// Several edits that are undone and redone together.
+    Batch(Vec<Edit>),
+}
+
+impl Edit {
+    /// Return the edit that undoes this one.
+    pub fn inverse(&self) -> Edit {
+        match self.clone() {
+            Edit::PlaceTile { tile, marbles } => Edit::DeleteTile { tile, marbles },
+            Edit::DeleteTile { tile, marbles } => Edit::PlaceTile { tile, marbles },
+            flip @ Edit::FlipTile { .. } => flip,
+            Edit::SetTileState { origin, from, to } => Edit::SetTileState {
+                origin,
+                from: to,
+                to: from,
+            },
+            Edit::PlaceMarble(placed) => Edit::RemoveMarble(placed),
+            Edit::RemoveMarble(placed) => Edit::PlaceMarble(placed),
+            Edit::Batch(edits) => Edit::Batch(edits.iter().rev().map(Edit::inverse).collect()),
+        }
+    }
+}
+
+/// Edits that can be undone, and undone edits that can be redone.
+#[derive(Resource, Default)]
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl History {
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+/// Apply an edit to the board, and record it in the undo history.
+#[derive(Event)]
+pub struct EditBoard(pub Edit);
+
+#[derive(Event)]
+pub struct Undo;
+
+#[derive(Event)]
+pub struct Redo;
+
+/// Handle the Ctrl+Z and Ctrl+Y (or Ctrl+Shift+Z) shortcuts.
+fn undo_keyboard(keyboard: Res<ButtonInput<KeyCode>>, mut commands: Commands) {
+    if !keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if keyboard.just_pressed(KeyCode::KeyZ) {
+        if shift {
+            commands.trigger(Redo);
+        } else {
+            commands.trigger(Undo);
+        }
+    }
+    if keyboard.just_pressed(KeyCode::KeyY) {
+        commands.trigger(Redo);
+    }
+}
+
+fn edit_board(trigger: Trigger<EditBoard>, mut editor: BoardEditor, mut history: ResMut<History>) {
+    let EditBoard(edit) = trigger.event();
+    editor.apply(edit);
+    history.undo.push(edit.clone());
+    history.redo.clear();
+}
+
+fn undo(_trigger: Trigger<Undo>, mut editor: BoardEditor, mut history: ResMut<History>) {
+    let Some(edit) = history.undo.pop() else {
+        debug!("nothing to undo");
+        return;
+    };
+    debug!("undo {edit:?}");
+    editor.apply(&edit.inverse());
+    history.redo.push(edit);
+}
+
+fn redo(_trigger: Trigger<Redo>, mut editor: BoardEditor, mut history: ResMut<History>) {
+    let Some(edit) = history.redo.pop() else {
+        debug!("nothing to redo");
+        return;
+    };
+    debug!("redo {edit:?}");
+    editor.apply(&edit);
+    history.undo.push(edit);
+}
+
+/// Access to the board entities, for applying edits.
+#[derive(SystemParam)]
+pub struct BoardEditor<'w, 's> {
+    commands: Commands<'w, 's>,
+    asset_server: Res<'w, AssetServer>,
+    tiles: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Tile,
+            &'static TileState,
+            &'static GridExtent,
+            &'static Sprite,
+        ),
+    >,
+    marbles: Query<'w, 's, (Entity, &'static GridPosition, &'static Marble)>,
+}
+
+impl BoardEditor<'_, '_> {
+    /// Make the changes described by an edit.
+    ///
+    /// Edits that refer to tiles or marbles that no longer exist are ignored.
+    pub fn apply(&mut self, edit: &Edit) {
+        match edit {
+            Edit::PlaceTile { tile, marbles } => {
+                spawn_tile(&mut self.commands, &self.asset_server, *tile);
+                for &placed in marbles {
+                    spawn_marble(&mut self.commands, &self.asset_server, placed);
+                }
+            }
+            Edit::DeleteTile { tile, .. } => {
+                self.despawn_tile(tile.extent.origin());
+            }
+            &Edit::FlipTile {
+                origin,
+                flip_x,
+                flip_y,
+            } => {
+                let Some((mut tile, marbles)) = self.despawn_tile(origin) else {
+                    return;
+                };
+                tile.flip_x ^= flip_x;
+                tile.flip_y ^= flip_y;
+                spawn_tile(&mut self.commands, &self.asset_server, tile);
+                for mut placed in marbles {
+                    placed.position = mirror(tile.extent, placed.position, flip_x, flip_y);
+                    spawn_marble(&mut self.commands, &self.asset_server, placed);
+                }
+            }
+            &Edit::SetTileState { origin, to, .. } => {
+                if let Some((entity, _)) = self.find_tile(origin) {
+                    self.commands.entity(entity).insert(to);
+                }
+            }
+            &Edit::PlaceMarble(placed) => {
+                spawn_marble(&mut self.commands, &self.asset_server, placed);
+            }
+            &Edit::RemoveMarble(placed) => {
+                for (entity, &marble_pos, _) in &self.marbles {
+                    if marble_pos == placed.position {
+                        self.commands.entity(entity).despawn();
+                    }
+                }
+            }
+            Edit::Batch(edits) => {
+                // Entities spawned by one edit aren't visible to later edits
+                // in the same batch, so a batch can't delete a tile it places.
+                for edit in edits {
+                    self.apply(edit);
+                }
+            }
+        }
+    }
+
+    /// Find the tile with its origin at `origin`.
+    pub fn find_tile(&self, origin: GridPosition) -> Option<(Entity, SimTile)> {
+        self.tiles
+            .iter()
+            .find(|(_, _, _, extent, _)| extent.origin() == origin)
+            .map(|(entity, &tile, &state, &extent, sprite)| {
+                let sim_tile = SimTile {
+                    tile,
+                    extent,
+                    flip_x: sprite.flip_x,
+                    flip_y: sprite.flip_y,
+                    state,
+                };
+                (entity, sim_tile)
+            })
+    }
+
+    /// Return the marbles sitting on a tile.
+    pub fn marbles_on(&self, extent: GridExtent) -> Vec<PlacedMarble> {
+        self.marbles
+            .iter()
+            .filter(|(_, position, _)| extent.contains_grid(**position))
+            .map(|(_, &position, &marble)| PlacedMarble { position, marble })
+            .collect()
+    }
+
+    /// Despawn a tile, along with its marble sockets and the marbles sitting on it.
+    ///
+    /// Returns the tile and marbles that were removed.
+    fn despawn_tile(&mut self, origin: GridPosition) -> Option<(SimTile, Vec<PlacedMarble>)> {
+        let Some((entity, tile)) = self.find_tile(origin) else {
+            debug!("no tile at {origin}");
+            return None;
+        };
+        let extent = tile.extent;
+        let marbles = self.marbles_on(extent);
+
+        // Despawning the tile also despawns its sockets.
+        self.commands.entity(entity).despawn();
+        for (entity, &pos, _) in &self.marbles {
+            if extent.contains_grid(pos) {
+                self.commands.entity(entity).despawn();
+            }
+        }
+        Some((tile, marbles))
+    }
+}
+
+/// Mirror a position within a tile extent, in the same way that flipping the tile does.
+fn mirror(extent: GridExtent, pos: GridPosition, flip_x: bool, flip_y: bool) -> GridPosition {
+    let GridPosition(origin) = extent.origin();
+    let GridPosition(mut pos) = pos;
+    if flip_x {
+        pos.x = 2 * origin.x + extent.width() - pos.x;
+    }
+    if flip_y {
+        pos.y = 2 * origin.y + GRID_UNITS_PER_TILE - pos.y;
+    }
+    GridPosition(pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::ivec2;
+
+    use super::*;
+    use crate::tile::{ALL_TILES, MarbleColor};
+
+    #[test]
+    fn mirror_matches_flipped_sockets() {
+        for &tile in ALL_TILES {
+            let extent = tile.extent(GridPosition(ivec2(-6, 4)));
+            for (flip_x, flip_y) in [(true, false), (false, true), (true, true)] {
+                for io_coord in tile.outputs().iter().chain(tile.sticky()) {
+                    let before = io_coord.to_grid(extent, false, false);
+                    let after = io_coord.to_grid(extent, flip_x, flip_y);
+                    assert_eq!(mirror(extent, before, flip_x, flip_y), after);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_round_trip() {
+        let tile = Tile::Switch;
+        let marble = PlacedMarble {
+            position: GridPosition(ivec2(2, 3)),
+            marble: Marble {
+                id: 4,
+                color: MarbleColor::Red,
+            },
+        };
+        let edits = [
+            Edit::PlaceTile {
+                tile: SimTile {
+                    tile,
+                    extent: tile.extent(GridPosition(ivec2(0, 0))),
+                    flip_x: true,
+                    flip_y: false,
+                    state: tile.initial_state(),
+                },
+                marbles: vec![marble],
+            },
+            Edit::FlipTile {
+                origin: GridPosition(ivec2(0, 0)),
+                flip_x: true,
+                flip_y: true,
+            },
+            Edit::SetTileState {
+                origin: GridPosition(ivec2(0, 0)),
+                from: tile.initial_state(),
+                to: tile.initial_state().next(),
+            },
+            Edit::PlaceMarble(marble),
+            Edit::Batch(vec![
+                Edit::RemoveMarble(marble),
+                Edit::FlipTile {
+                    origin: GridPosition(ivec2(0, 0)),
+                    flip_x: false,
+                    flip_y: true,
+                },
+            ]),
+        ];
+        for edit in edits {
+            assert_eq!(edit.inverse().inverse(), edit);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Exporting pictures of the whole board.
+//!
+//! A PNG is drawn with the same sprites as the editor, scaled up by the export
+//! scale. An SVG shows the tile outlines, flips and marbles as shapes. Either
+//! is written next to the board file, with its extension changed.
+//!
+//! A run can also be recorded as an animated GIF or APNG, from the board as it
+//! was when Play was pressed, at the export scale and the current speed.
+
+use bevy::prelude::*;
+
+use crate::{
+    export::{self, Sprites},
+    grid::GridPosition,
+    record::{self, AnimationFormat, RecordOptions},
+    save_load::{BoardPath, read_board, write_file},
+    simulate::{SimSpeed, Snapshot},
+    tile::{ALL_TILES, GridExtent, Marble, Tile, TileState},
+    ui::UiExportScaleText,
+};
+
+/// The scales that the export scale cycles through.
+const EXPORT_SCALES: &[u32] = &[1, 2, 4, 8];
+
+pub struct ImageExportPlugin;
+
+impl Plugin for ImageExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ExportScale(4))
+            .add_systems(Startup, load_export_sprites)
+            .add_systems(
+                Update,
+                show_export_scale.run_if(resource_changed::<ExportScale>),
+            )
+            .add_observer(export_image)
+            .add_observer(record_run);
+    }
+}
+
+/// How many image pixels each world pixel becomes in an exported PNG.
+#[derive(Resource)]
+pub struct ExportScale(pub u32);
+
+impl ExportScale {
+    /// Switch to the next scale, wrapping around to the smallest.
+    pub fn cycle(&mut self) {
+        let index = EXPORT_SCALES.iter().position(|&scale| scale == self.0);
+        self.0 = EXPORT_SCALES[index.map_or(0, |index| (index + 1) % EXPORT_SCALES.len())];
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Svg,
+}
+
+impl ImageFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Svg => "svg",
+        }
+    }
+}
+
+/// Export a picture of the board.
+#[derive(Event)]
+pub struct ExportImage(pub ImageFormat);
+
+/// Record a run of the board as an animation.
+#[derive(Event)]
+pub struct RecordRun(pub AnimationFormat);
+
+/// Handles to the sprites used in exported pictures, so they stay loaded.
+#[derive(Resource)]
+struct ExportSprites {
+    tiles: Vec<(Tile, Handle<Image>)>,
+    marble: Handle<Image>,
+}
+
+fn load_export_sprites(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(ExportSprites {
+        tiles: ALL_TILES
+            .iter()
+            .map(|&tile| (tile, asset_server.load(tile.sprite_filename())))
+            .collect(),
+        marble: asset_server.load(Marble::sprite_filename()),
+    });
+}
+
+/// Copy the loaded sprite images, or `None` if any are still loading.
+fn collect_sprites(handles: &ExportSprites, images: &Assets<Image>) -> Option<Sprites> {
+    let convert = |handle: &Handle<Image>| {
+        let image = images.get(handle)?.clone();
+        Some(image.try_into_dynamic().ok()?.into_rgba8())
+    };
+    let mut sprites = Sprites::new(convert(&handles.marble)?);
+    for (tile, handle) in &handles.tiles {
+        sprites.insert_tile(*tile, convert(handle)?);
+    }
+    Some(sprites)
+}
+
+fn export_image(
+    trigger: Trigger<ExportImage>,
+    path: Res<BoardPath>,
+    scale: Res<ExportScale>,
+    handles: Res<ExportSprites>,
+    images: Res<Assets<Image>>,
+    tiles: Query<(&Tile, &TileState, &GridExtent, &Sprite)>,
+    marbles: Query<(&GridPosition, &Marble)>,
+) {
+    let format = trigger.0;
+    let board = read_board(&tiles, &marbles);
+    let contents = match format {
+        ImageFormat::Png => {
+            let Some(sprites) = collect_sprites(&handles, &images) else {
+                error!("can't export a PNG until the sprites have loaded");
+                return;
+            };
+            export::render_png(&board, &sprites, scale.0)
+                .and_then(|image| export::encode_png(&image))
+        }
+        ImageFormat::Svg => export::render_svg(&board, scale.0).map(String::into_bytes),
+    };
+    let contents = match contents {
+        Ok(contents) => contents,
+        Err(e) => {
+            error!("failed to export the board: {e}");
+            return;
+        }
+    };
+    let path = path.0.with_extension(format.extension());
+    match write_file(&path, contents) {
+        Ok(()) => info!("exported board to {}", path.display()),
+        Err(e) => error!("failed to export board to {}: {e}", path.display()),
+    }
+}
+
+#[expect(clippy::too_many_arguments)]
+fn record_run(
+    trigger: Trigger<RecordRun>,
+    path: Res<BoardPath>,
+    scale: Res<ExportScale>,
+    speed: Res<SimSpeed>,
+    snapshot: Option<Res<Snapshot>>,
+    handles: Res<ExportSprites>,
+    images: Res<Assets<Image>>,
+    tiles: Query<(&Tile, &TileState, &GridExtent, &Sprite)>,
+    marbles: Query<(&GridPosition, &Marble)>,
+) {
+    let format = trigger.0;
+    // Once Play has been pressed, the board on screen is part way through the run.
+    let board = match snapshot {
+        Some(snapshot) => snapshot.0.clone(),
+        None => read_board(&tiles, &marbles),
+    };
+    let Some(sprites) = collect_sprites(&handles, &images) else {
+        error!("can't record a run until the sprites have loaded");
+        return;
+    };
+    let options = RecordOptions {
+        scale: scale.0,
+        ticks_per_second: speed.ticks_per_second(),
+        ..RecordOptions::default()
+    };
+    let contents = match record::record(&board, &sprites, format, options) {
+        Ok(contents) => contents,
+        Err(e) => {
+            error!("failed to record the run: {e}");
+            return;
+        }
+    };
+    let path = path.0.with_extension(format.extension());
+    match write_file(&path, contents) {
+        Ok(()) => info!("recorded run to {}", path.display()),
+        Err(e) => error!("failed to record run to {}: {e}", path.display()),
+    }
+}
+
+/// Update the export scale display.
+fn show_export_scale(scale: Res<ExportScale>, mut text: Query<&mut Text, With<UiExportScaleText>>) {
+    for mut text in &mut text {
+        text.0 = format!("{}x", scale.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_scale_cycles() {
+        let mut scale = ExportScale(4);
+        scale.cycle();
+        assert_eq!(scale.0, 8);
+        scale.cycle();
+        assert_eq!(scale.0, 1);
+        let mut odd = ExportScale(3);
+        odd.cycle();
+        assert_eq!(odd.0, 1);
+    }
+}
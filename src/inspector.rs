@@ -0,0 +1,377 @@
+//! A side panel describing a placed tile.
+//!
+//! Clicking a tile while the board is idle or paused shows its kind, origin,
+//! width, flips, internal state, and the grid positions of its inputs and
+//! outputs. While idle, the flips and state can be changed from the panel, as
+//! edits that can be undone. While paused, the state can be changed, and the
+//! simulation carries on from the new state. Locked puzzle tiles can only be
+//! looked at. Clicking away from any tile closes the panel.
+
+use bevy::prelude::*;
+
+use crate::{
+    MainCamera, MouseClick, SimState,
+    grid::GridPosition,
+    history::{Edit, EditBoard},
+    puzzle_mode::Locked,
+    sim::SimTile,
+    simulate::ActiveSimulation,
+    tile::{GridExtent, Tile, TileState},
+};
+
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Inspected>().add_systems(
+            Update,
+            (
+                inspect_clicked_tile
+                    .run_if(in_state(SimState::Idle).or(in_state(SimState::Paused))),
+                close_inspector.run_if(not(in_state(SimState::Idle)
+                    .or(in_state(SimState::Paused))
+                    .or(in_state(SimState::Running)))),
+                inspector_button_click,
+                show_inspector,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// The origin of the tile shown in the inspector, if any.
+///
+/// Tiles are found by their origin, because flipping a tile or undoing an
+/// edit spawns new entities.
+#[derive(Resource, Default)]
+pub struct Inspected(pub Option<GridPosition>);
+
+/// The inspector panel.
+#[derive(Component)]
+struct InspectorPanel;
+
+#[derive(Clone, Copy, Debug, Component)]
+enum InspectorButton {
+    Close,
+    FlipX,
+    FlipY,
+    /// Switch the tile to its next internal state.
+    NextState,
+}
+
+/// Everything the panel shows, so that it is only rebuilt when something changes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct InspectorView {
+    tile: SimTile,
+    locked: bool,
+    mode: SimState,
+}
+
+impl InspectorView {
+    /// The labelled lines of the panel.
+    fn lines(&self) -> Vec<(&'static str, String)> {
+        let tile = self.tile;
+        let yes_no = |flag: bool| if flag { "yes" } else { "no" }.to_owned();
+        let mut lines = vec![
+            ("tile", tile.tile.name().to_owned()),
+            ("origin", tile.extent.origin().to_string()),
+            ("width", tile.extent.width().to_string()),
+            ("flip x", yes_no(tile.flip_x)),
+            ("flip y", yes_no(tile.flip_y)),
+            ("state", tile.state.to_string()),
+            ("inputs", list_positions(tile.inputs())),
+            ("outputs", list_positions(tile.outputs())),
+        ];
+        if self.locked {
+            lines.push(("locked", "yes".to_owned()));
+        }
+        lines
+    }
+
+    /// The edits the panel offers. Flipping would change the routes of a
+    /// paused simulation, so only the state can be changed then.
+    fn buttons(&self) -> Vec<(InspectorButton, &'static str)> {
+        let mut buttons = Vec::new();
+        let has_state = self.tile.state != TileState::Stateless;
+        if !self.locked {
+            if self.mode == SimState::Idle {
+                buttons.push((InspectorButton::FlipX, "Flip X"));
+                buttons.push((InspectorButton::FlipY, "Flip Y"));
+            }
+            if has_state && matches!(self.mode, SimState::Idle | SimState::Paused) {
+                buttons.push((InspectorButton::NextState, "State"));
+            }
+        }
+        buttons.push((InspectorButton::Close, "Close"));
+        buttons
+    }
+}
+
+/// A list of grid positions, separated by spaces.
+fn list_positions(positions: impl Iterator<Item = GridPosition>) -> String {
+    let positions: Vec<String> = positions.map(|pos| pos.to_string()).collect();
+    positions.join(" ")
+}
+
+/// Inspect the tile under a click, or close the panel if there isn't one.
+#[expect(clippy::type_complexity)]
+fn inspect_clicked_tile(
+    mut clicks: EventReader<MouseClick>,
+    panel: Query<&Interaction, Or<(With<InspectorPanel>, With<InspectorButton>)>>,
+    tiles: Query<&GridExtent, With<Tile>>,
+    mut inspected: ResMut<Inspected>,
+) {
+    for click in clicks.read() {
+        // Clicks on the panel are handled by its buttons.
+        if panel
+            .iter()
+            .any(|&interaction| interaction != Interaction::None)
+        {
+            continue;
+        }
+        inspected.0 = tiles
+            .iter()
+            .find(|extent| extent.contains(click.world_pos))
+            .map(GridExtent::origin);
+    }
+}
+
+/// Close the panel when switching to an editing mode.
+fn close_inspector(mut inspected: ResMut<Inspected>) {
+    if inspected.0.is_some() {
+        inspected.0 = None;
+    }
+}
+
+#[expect(clippy::type_complexity)]
+fn inspector_button_click(
+    buttons: Query<(&Interaction, &InspectorButton), (Changed<Interaction>, With<Button>)>,
+    mut inspected: ResMut<Inspected>,
+    state: Res<State<SimState>>,
+    tiles: Query<(Entity, &TileState, &GridExtent), (With<Tile>, Without<Locked>)>,
+    mut active: Option<ResMut<ActiveSimulation>>,
+    mut commands: Commands,
+) {
+    for (interaction, &button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(origin) = inspected.0 else {
+            continue;
+        };
+        if let InspectorButton::Close = button {
+            inspected.0 = None;
+            continue;
+        }
+        let Some((entity, &tile_state, _)) = tiles
+            .iter()
+            .find(|(_, _, extent)| extent.origin() == origin)
+        else {
+            continue;
+        };
+        match (button, state.get()) {
+            (InspectorButton::FlipX | InspectorButton::FlipY, SimState::Idle) => {
+                commands.trigger(EditBoard(Edit::FlipTile {
+                    origin,
+                    flip_x: matches!(button, InspectorButton::FlipX),
+                    flip_y: matches!(button, InspectorButton::FlipY),
+                }));
+            }
+            (InspectorButton::NextState, SimState::Idle) => {
+                commands.trigger(EditBoard(Edit::SetTileState {
+                    origin,
+                    from: tile_state,
+                    to: tile_state.next(),
+                }));
+            }
+            (InspectorButton::NextState, SimState::Paused) => {
+                let next = tile_state.next();
+                if let Some(active) = &mut active {
+                    active.set_tile_state(entity, next);
+                }
+                commands.entity(entity).insert(next);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Rebuild the panel when the inspected tile changes, or close it if the tile is gone.
+#[expect(clippy::too_many_arguments)]
+fn show_inspector(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut inspected: ResMut<Inspected>,
+    state: Res<State<SimState>>,
+    tiles: Query<(&Tile, &TileState, &GridExtent, &Sprite, Has<Locked>)>,
+    panels: Query<Entity, With<InspectorPanel>>,
+    camera: Single<Entity, With<MainCamera>>,
+    mut shown: Local<Option<InspectorView>>,
+) {
+    let view = inspected.0.and_then(|origin| {
+        tiles
+            .iter()
+            .find(|(_, _, extent, _, _)| extent.origin() == origin)
+            .map(
+                |(&tile, &tile_state, &extent, sprite, locked)| InspectorView {
+                    tile: SimTile {
+                        tile,
+                        extent,
+                        flip_x: sprite.flip_x,
+                        flip_y: sprite.flip_y,
+                        state: tile_state,
+                    },
+                    locked,
+                    mode: *state.get(),
+                },
+            )
+    });
+    if view.is_none() && inspected.0.is_some() {
+        debug!("inspected tile is gone");
+        inspected.0 = None;
+    }
+    if *shown == view {
+        return;
+    }
+    *shown = view;
+
+    for panel in &panels {
+        commands.entity(panel).despawn();
+    }
+    let Some(view) = view else {
+        return;
+    };
+    let font = TextFont {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 3.0,
+        ..default()
+    };
+    commands
+        .spawn((
+            InspectorPanel,
+            UiTargetCamera(*camera),
+            Interaction::default(),
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(2.0),
+                right: Val::Px(2.0),
+                display: Display::Flex,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(1.0),
+                padding: UiRect::all(Val::Px(2.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    display: Display::Grid,
+                    grid_template_columns: vec![GridTrack::auto(), GridTrack::auto()],
+                    column_gap: Val::Px(3.0),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (label, value) in view.lines() {
+                        parent.spawn((Text::new(label), font.clone()));
+                        parent.spawn((Text::new(value), font.clone()));
+                    }
+                });
+            parent
+                .spawn(Node {
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Row,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (button, caption) in view.buttons() {
+                        parent
+                            .spawn((
+                                button,
+                                Button,
+                                Node {
+                                    min_width: Val::Px(10.),
+                                    height: Val::Px(6.),
+                                    border: UiRect::all(Val::Px(0.5)),
+                                    padding: UiRect::horizontal(Val::Px(1.0)),
+                                    margin: UiRect::right(Val::Px(1.0)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                BorderColor(Color::WHITE),
+                                BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+                            ))
+                            .with_child((Text::new(caption), font.clone()));
+                    }
+                });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::ivec2;
+
+    use super::*;
+
+    fn view(tile: Tile, locked: bool, mode: SimState) -> InspectorView {
+        InspectorView {
+            tile: SimTile {
+                tile,
+                extent: tile.extent(GridPosition(ivec2(2, 4))),
+                flip_x: true,
+                flip_y: false,
+                state: tile.initial_state(),
+            },
+            locked,
+            mode,
+        }
+    }
+
+    fn buttons(view: InspectorView) -> Vec<&'static str> {
+        view.buttons()
+            .into_iter()
+            .map(|(_, caption)| caption)
+            .collect()
+    }
+
+    #[test]
+    fn lines_describe_the_tile() {
+        let lines = view(Tile::Switch, false, SimState::Idle).lines();
+        let line = |label| {
+            lines
+                .iter()
+                .find(|(l, _)| *l == label)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(line("tile"), Some("switch"));
+        assert_eq!(line("origin"), Some("<2, 4>"));
+        assert_eq!(line("width"), Some("8"));
+        assert_eq!(line("flip x"), Some("yes"));
+        assert_eq!(line("state"), Some("lever straight"));
+        let inputs = line("inputs").unwrap();
+        assert_eq!(inputs.matches('<').count(), Tile::Switch.inputs().len());
+        assert_eq!(line("locked"), None);
+    }
+
+    #[test]
+    fn buttons_follow_the_mode() {
+        assert_eq!(
+            buttons(view(Tile::Switch, false, SimState::Idle)),
+            ["Flip X", "Flip Y", "State", "Close"]
+        );
+        assert_eq!(
+            buttons(view(Tile::Path, false, SimState::Idle)),
+            ["Flip X", "Flip Y", "Close"]
+        );
+        assert_eq!(
+            buttons(view(Tile::Switch, false, SimState::Paused)),
+            ["State", "Close"]
+        );
+        assert_eq!(
+            buttons(view(Tile::Switch, false, SimState::Running)),
+            ["Close"]
+        );
+        assert_eq!(buttons(view(Tile::Switch, true, SimState::Idle)), ["Close"]);
+    }
+}
@@ -0,0 +1,14 @@
+//! The board model and marble simulation.
+//!
+//! None of this needs a window or a GPU, so it is shared by the editor and the
+//! headless command-line runner.
+
+pub mod board;
+pub mod export;
+pub mod grid;
+pub mod puzzle;
+pub mod record;
+pub mod report;
+pub mod share;
+pub mod sim;
+pub mod tile;
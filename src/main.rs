@@ -1,17 +1,47 @@
 use bevy::prelude::*;
 use bevy::render::camera::Viewport;
 use bevy::window::{PresentMode, PrimaryWindow, WindowResized, WindowResolution};
+use blueprint::BlueprintPlugin;
+use camera::CameraPlugin;
+use controller::ControllerPlugin;
+use grid_overlay::GridOverlayPlugin;
+use history::HistoryPlugin;
+use image_export::ImageExportPlugin;
+use inspector::InspectorPlugin;
 use place_marble::MarblePlacePlugin;
 use place_tile::TilePlacePlugin;
+use puzzle_mode::PuzzlePlugin;
+use save_load::SaveLoadPlugin;
+use select::SelectPlugin;
+use share_link::ShareLinkPlugin;
+use shortcuts::ShortcutsPlugin;
+use simulate::SimulatePlugin;
+use trail::TrailPlugin;
 use ui::{
-    UI_PANEL_HEIGHT, UiTileSelected, action_button_click, init_ui, marble_button_click,
+    UI_PANEL_HEIGHT, UiTileSelected, action_button_click, blueprint_button_click, init_ui,
+    marble_button_click, marble_color_button_click, run_action, show_marble_color, show_sim_speed,
     tile_button_click,
 };
 
-mod grid;
+use roonsim::{board, export, grid, puzzle, record, share, sim, tile};
+
+mod animate;
+mod blueprint;
+mod camera;
+mod controller;
+mod grid_overlay;
+mod history;
+mod image_export;
+mod inspector;
 mod place_marble;
 mod place_tile;
-mod tile;
+mod puzzle_mode;
+mod save_load;
+mod select;
+mod share_link;
+mod shortcuts;
+mod simulate;
+mod trail;
 mod ui;
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, States)]
@@ -24,6 +54,12 @@ enum SimState {
     Deleting,
     /// Placing marbles.
     PlacingMarbles,
+    /// Deleting marbles.
+    DeletingMarbles,
+    /// Selecting, moving and copying groups of tiles.
+    Selecting,
+    /// Stamping copies of a blueprint.
+    Stamping,
     /// Game is paused mid-simulation.
     Paused,
     /// Game simulation is running.
@@ -63,10 +99,28 @@ fn main() {
                 })
                 .build(),
         )
-        .add_plugins((TilePlacePlugin, MarblePlacePlugin))
+        .add_plugins((
+            TilePlacePlugin,
+            MarblePlacePlugin,
+            SimulatePlugin,
+            SaveLoadPlugin,
+            HistoryPlugin,
+            CameraPlugin,
+            PuzzlePlugin,
+            TrailPlugin,
+            SelectPlugin,
+            BlueprintPlugin,
+            GridOverlayPlugin,
+            ShortcutsPlugin,
+            ControllerPlugin,
+            ShareLinkPlugin,
+            ImageExportPlugin,
+        ))
+        .add_plugins(InspectorPlugin)
         .insert_resource(ClearColor(Color::srgb(0.3, 0.3, 0.3)))
         .add_event::<MouseClick>()
         .add_event::<UiTileSelected>()
+        .add_observer(run_action)
         .init_state::<SimState>()
         .add_systems(Startup, setup)
         .add_systems(
@@ -74,7 +128,11 @@ fn main() {
             (
                 tile_button_click,
                 marble_button_click,
+                marble_color_button_click,
+                blueprint_button_click,
+                show_marble_color,
                 action_button_click,
+                show_sim_speed,
                 on_resize_system,
                 mouse_button_input,
             ),
@@ -86,7 +144,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     // FIXME: unify this code with the window resize code.
     let viewport = Viewport {
         physical_position: UVec2::new(0, UI_PANEL_HEIGHT),
-        physical_size: UVec2::new(800, 700),
+        physical_size: UVec2::new(800, 800 - UI_PANEL_HEIGHT),
         ..default()
     };
     let camera = Camera {
@@ -136,24 +194,24 @@ fn mouse_button_input(
     window: Single<&Window, With<PrimaryWindow>>,
     q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
 ) {
-    if buttons.just_pressed(MouseButton::Left) {
-        if let Some(cursor) = window.cursor_position() {
-            let (camera, camera_transform) = q_camera.single().unwrap();
-
-            let viewport_rect = camera.logical_viewport_rect().unwrap();
-            if !viewport_rect.contains(cursor) {
-                // click is outside viewport.
-                // It seems a bit silly that viewport_to_world_2d doesn't
-                // handle this.
-                return;
-            }
-
-            let world_pos = camera
-                .viewport_to_world_2d(camera_transform, cursor)
-                .unwrap();
-
-            debug!("left click, window coords {cursor} world coords {world_pos}",);
-            event_writer.write(MouseClick { world_pos });
+    if buttons.just_pressed(MouseButton::Left)
+        && let Some(cursor) = window.cursor_position()
+    {
+        let (camera, camera_transform) = q_camera.single().unwrap();
+
+        let viewport_rect = camera.logical_viewport_rect().unwrap();
+        if !viewport_rect.contains(cursor) {
+            // click is outside viewport.
+            // It seems a bit silly that viewport_to_world_2d doesn't
+            // handle this.
+            return;
         }
+
+        let world_pos = camera
+            .viewport_to_world_2d(camera_transform, cursor)
+            .unwrap();
+
+        debug!("left click, window coords {cursor} world coords {world_pos}",);
+        event_writer.write(MouseClick { world_pos });
     }
 }
@@ -2,21 +2,38 @@ use bevy::prelude::*;
 
 use crate::{
     MainCamera, MouseClick, SimState,
+    animate::MarbleMotion,
+    board::PlacedMarble,
     grid::GridPosition,
-    tile::{GridExtent, Marble, Tile},
+    history::{Edit, EditBoard},
+    puzzle_mode::ActivePuzzle,
+    sim::SimTile,
+    tile::{Marble, MarbleColor},
 };
 
 pub struct MarblePlacePlugin;
 
 impl Plugin for MarblePlacePlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<DespawnMarble>()
+        app.init_resource::<SelectedMarbleColor>()
+            .add_event::<DespawnMarble>()
             .add_event::<ShowMarbleSockets>()
             .add_systems(
                 Update,
-                (marble_placement_cursor_moved, mouseclick_place_marble)
+                (
+                    marble_placement_cursor_moved,
+                    tint_ghost_marble,
+                    // A puzzle's starting marbles are fixed.
+                    mouseclick_place_marble.run_if(not(resource_exists::<ActivePuzzle>)),
+                )
                     .run_if(in_state(SimState::PlacingMarbles)),
             )
+            .add_systems(
+                Update,
+                mouseclick_delete_marble.run_if(
+                    in_state(SimState::DeletingMarbles).and(not(resource_exists::<ActivePuzzle>)),
+                ),
+            )
             .add_systems(OnEnter(SimState::PlacingMarbles), spawn_ghost_marble)
             .add_systems(OnExit(SimState::PlacingMarbles), despawn_ghost_marble)
             .add_observer(show_marble_sockets);
@@ -52,17 +69,32 @@ pub fn marble_placement_cursor_moved(
     }
 }
 
+/// The colour of the marbles being placed.
+#[derive(Resource, Default)]
+pub struct SelectedMarbleColor(pub MarbleColor);
+
 pub fn mouseclick_place_marble(
     mut event_reader: EventReader<MouseClick>,
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
+    color: Res<SelectedMarbleColor>,
     sockets: Query<&GridPosition, With<MarbleSocket>>,
-    existing_marbles: Query<&GridPosition, With<Marble>>,
+    existing_marbles: Query<(&GridPosition, &Marble)>,
 ) {
     for mouse_click in event_reader.read() {
-        // Compute the world position of the new marble.
+        // Compute the grid position of the new marble.
         let grid_pos = GridPosition::from_world(mouse_click.world_pos);
-        let position = grid_pos.to_world();
+
+        // A click on an existing marble removes it.
+        if let Some((&position, &marble)) =
+            existing_marbles.iter().find(|(pos, _)| **pos == grid_pos)
+        {
+            debug!("remove marble");
+            commands.trigger(EditBoard(Edit::RemoveMarble(PlacedMarble {
+                position,
+                marble,
+            })));
+            return;
+        }
 
         // Check if the position contains a socket.
         if !sockets
@@ -73,10 +105,8 @@ pub fn mouseclick_place_marble(
             return;
         }
 
-        // FIXME: maybe a click on an existing marble should delete it?
-
         // Check if the new tile collides with any existing marbles.
-        for &existing_grid_pos in existing_marbles {
+        for (&existing_grid_pos, _) in &existing_marbles {
             let (x, y) = grid_pos.distance_to(existing_grid_pos).into();
             // FIXME: this inequality may be silly, as there are
             // no marble sockets 1 unit away from one another.
@@ -86,55 +116,92 @@ pub fn mouseclick_place_marble(
             }
         }
 
-        debug!("spawn marble");
-
-        // why -0.1 ? We need a bunch of constants for our Z heights.
-        let position: Vec3 = (position, -0.1).into();
+        let marble = Marble {
+            id: Marble::next_id(existing_marbles.iter().map(|(_, &marble)| marble)),
+            color: color.0,
+        };
+        debug!("spawn marble {marble:?}");
+        commands.trigger(EditBoard(Edit::PlaceMarble(PlacedMarble {
+            position: grid_pos,
+            marble,
+        })));
+    }
+}
 
-        let sprite = Marble::load_sprite(&asset_server);
-        commands.spawn((
-            sprite,
-            Transform::from_translation(position),
-            grid_pos,
-            Marble,
-        ));
+pub fn mouseclick_delete_marble(
+    mut event_reader: EventReader<MouseClick>,
+    mut commands: Commands,
+    existing_marbles: Query<(&GridPosition, &Marble)>,
+) {
+    for mouse_click in event_reader.read() {
+        let grid_pos = GridPosition::from_world(mouse_click.world_pos);
+        if let Some((&position, &marble)) =
+            existing_marbles.iter().find(|(pos, _)| **pos == grid_pos)
+        {
+            debug!("deleting marble");
+            commands.trigger(EditBoard(Edit::RemoveMarble(PlacedMarble {
+                position,
+                marble,
+            })));
+        }
     }
 }
 
+/// Spawn a marble entity, tinted with its colour.
+pub fn spawn_marble(commands: &mut Commands, asset_server: &AssetServer, placed: PlacedMarble) {
+    let PlacedMarble {
+        position: grid_pos,
+        marble,
+    } = placed;
+    // why -0.1 ? We need a bunch of constants for our Z heights.
+    let position: Vec3 = (grid_pos.to_world(), -0.1).into();
+
+    let mut sprite = Marble::load_sprite(asset_server);
+    sprite.color = marble.color.tint();
+    commands.spawn((
+        sprite,
+        Transform::from_translation(position),
+        grid_pos,
+        marble,
+        MarbleMotion::stationary(grid_pos),
+    ));
+}
+
 #[derive(Component)]
 pub struct MarbleSocket;
 
-/// Place MarbleSocket entities.
+/// Place MarbleSocket entities as children of a tile entity.
 ///
-/// Marble sockets mark the places where it is legal to place marbles.
-/// They are invisible (Disabled) unless we're in the marble placement
-/// state.
+/// Marble sockets mark the places where it is legal to place marbles:
+/// the tile's outputs and sticky points. They are invisible (Disabled)
+/// unless we're in the marble placement state. Being children of the tile,
+/// they are despawned along with it.
 pub fn place_marble_sockets(
     commands: &mut Commands,
     asset_server: &AssetServer,
-    tile: Tile,
-    extent: GridExtent,
-    flip_x: bool,
-    flip_y: bool,
+    tile_entity: Entity,
+    placed: SimTile,
 ) {
     // FIXME: needs a better name.
     let sprite = Sprite::from_image(asset_server.load("output.png"));
 
-    // FIXME: this entity should be a child of the tile entity.
-    for io_coord in tile.outputs() {
-        let grid_position = io_coord.to_grid(extent, flip_x, flip_y);
-        let position = grid_position.to_world();
-        let position: Vec3 = (position, -0.5).into();
-        commands.spawn((
-            sprite.clone(),
-            Transform::from_translation(position),
-            grid_position,
-            MarbleSocket,
-            // NOTE: bevy #18981 makes `Disabled` not work correctly if it's attached
-            // to the entity at spawn time.
-            Visibility::Hidden,
-        ));
-    }
+    let tile_origin = placed.extent.origin().to_world();
+    commands.entity(tile_entity).with_children(|parent| {
+        for grid_position in placed.outputs().chain(placed.sticky()) {
+            // The tile is at z -1.0, and sockets go at -0.5.
+            let position = grid_position.to_world() - tile_origin;
+            let position: Vec3 = (position, 0.5).into();
+            parent.spawn((
+                sprite.clone(),
+                Transform::from_translation(position),
+                grid_position,
+                MarbleSocket,
+                // NOTE: bevy #18981 makes `Disabled` not work correctly if it's attached
+                // to the entity at spawn time.
+                Visibility::Hidden,
+            ));
+        }
+    });
 }
 
 #[derive(Event)]
@@ -178,6 +245,20 @@ pub fn spawn_ghost_marble(mut commands: Commands, asset_server: Res<AssetServer>
     commands.trigger(ShowMarbleSockets(true));
 }
 
+/// Show the selected colour on the ghost marble.
+fn tint_ghost_marble(
+    color: Res<SelectedMarbleColor>,
+    mut ghost: Query<&mut Sprite, With<GhostMarble>>,
+) {
+    for mut sprite in &mut ghost {
+        // Translucent, to differentiate it from the already-placed marbles.
+        let tint = color.0.tint().with_alpha(0.3);
+        if sprite.color != tint {
+            sprite.color = tint;
+        }
+    }
+}
+
 pub fn despawn_ghost_marble(mut commands: Commands, mut ghost: Query<Entity, With<GhostMarble>>) {
     // Despawn the previous ghost marble, if any.
     if let Ok(ghost_entity) = ghost.single_mut() {
@@ -1,13 +1,30 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, window::PrimaryWindow};
 
 use crate::{
     MainCamera, MouseClick, SimState,
-    grid::GridPosition,
+    board::PlacedMarble,
+    grid::{GRID_UNITS_PER_TILE, GridPosition, PIXELS_PER_GRID_UNIT},
+    history::{Edit, EditBoard},
     place_marble::place_marble_sockets,
-    tile::{GridExtent, Offset, Tile},
+    puzzle_mode::{ActivePuzzle, Locked, inventory_exhausted},
+    sim::SimTile,
+    simulate::state_indicator,
+    tile::{GridExtent, Marble, Offset, Tile, TileState},
     ui::UiTileSelected,
 };
 
+/// The ghost tile's colour where it can be placed.
+const GHOST_LEGAL_TINT: Color = Color::linear_rgba(1.0, 1.0, 1.0, 0.3);
+
+/// The ghost tile's colour where it would collide with another tile, or isn't in the inventory.
+const GHOST_BLOCKED_TINT: Color = Color::linear_rgba(1.0, 0.2, 0.2, 0.5);
+
+/// The outline of the grid cells the ghost tile would cover, when it can be placed.
+const GHOST_LEGAL_OUTLINE: Color = Color::srgb(0.4, 1.0, 0.4);
+
+/// The outline of the grid cells the ghost tile would cover, when it can't be placed.
+const GHOST_BLOCKED_OUTLINE: Color = Color::srgb(1.0, 0.3, 0.3);
+
 pub struct TilePlacePlugin;
 
 impl Plugin for TilePlacePlugin {
@@ -19,15 +36,20 @@ impl Plugin for TilePlacePlugin {
                     placing_keyboard,
                     tile_placement_cursor_moved,
                     mouseclick_place_tile,
+                    preview_ghost_tile,
                 )
+                    .chain()
                     .run_if(in_state(SimState::Placing)),
             )
             .add_systems(
                 Update,
                 mouseclick_delete_tile.run_if(in_state(SimState::Deleting)),
             )
+            .add_systems(Update, flip_hovered_tile.run_if(in_state(SimState::Idle)))
             .add_observer(spawn_ghost_tile)
-            .add_observer(despawn_ghost_tile);
+            .add_observer(despawn_ghost_tile)
+            .add_observer(flip_tile_at)
+            .add_observer(delete_tile_at);
     }
 }
 
@@ -57,73 +79,207 @@ pub fn placing_keyboard(
     }
 }
 
-#[expect(clippy::type_complexity)]
-pub fn mouseclick_delete_tile(
-    mut event_reader: EventReader<MouseClick>,
-    existing_tiles: Query<(Entity, &GridExtent), (With<Tile>, Without<GhostTile>)>,
+/// Flip the placed tile under the mouse cursor, using the same keys as the ghost tile.
+pub fn flip_hovered_tile(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut commands: Commands,
+) {
+    let flip_x = keyboard.just_pressed(KeyCode::ArrowLeft);
+    let flip_y = keyboard.just_pressed(KeyCode::ArrowUp);
+    if !(flip_x || flip_y) {
+        return;
+    }
+    let (camera, camera_transform) = *camera;
+    let Some(world_pos) = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor).ok())
+    else {
+        return;
+    };
+    commands.trigger(FlipTileAt {
+        world_pos,
+        flip_x,
+        flip_y,
+    });
+}
+
+/// Flip the placed tile at a world position, if there is one.
+#[derive(Event)]
+pub struct FlipTileAt {
+    pub world_pos: Vec2,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+fn flip_tile_at(
+    trigger: Trigger<FlipTileAt>,
+    tiles: Query<&GridExtent, (With<Tile>, Without<Locked>)>,
     mut commands: Commands,
 ) {
+    let FlipTileAt {
+        world_pos,
+        flip_x,
+        flip_y,
+    } = *trigger;
+    if let Some(extent) = tiles.iter().find(|extent| extent.contains(world_pos)) {
+        commands.trigger(EditBoard(Edit::FlipTile {
+            origin: extent.origin(),
+            flip_x,
+            flip_y,
+        }));
+    }
+}
+
+pub fn mouseclick_delete_tile(mut event_reader: EventReader<MouseClick>, mut commands: Commands) {
     for mouse_click in event_reader.read() {
-        // Search for a tile that intersects the click position.
-        for (entity, extent) in existing_tiles {
-            if extent.contains(mouse_click.world_pos) {
-                debug!("deleting tile");
-                commands.entity(entity).despawn();
-                break;
-            }
+        commands.trigger(DeleteTileAt(mouse_click.world_pos));
+    }
+}
+
+/// Delete the placed tile at a world position, along with its marbles, if there is one.
+#[derive(Event)]
+pub struct DeleteTileAt(pub Vec2);
+
+fn delete_tile_at(
+    trigger: Trigger<DeleteTileAt>,
+    existing_tiles: Query<(&Tile, &TileState, &GridExtent, &Sprite), Without<Locked>>,
+    marbles: Query<(&GridPosition, &Marble)>,
+    mut commands: Commands,
+) {
+    let DeleteTileAt(world_pos) = *trigger;
+    // Search for a tile that intersects the position.
+    for (&tile, &state, &extent, sprite) in &existing_tiles {
+        if extent.contains(world_pos) {
+            debug!("deleting tile");
+            let marbles = marbles
+                .iter()
+                .filter(|(position, _)| extent.contains_grid(**position))
+                .map(|(&position, &marble)| PlacedMarble { position, marble })
+                .collect();
+            let tile = SimTile {
+                tile,
+                extent,
+                flip_x: sprite.flip_x,
+                flip_y: sprite.flip_y,
+                state,
+            };
+            commands.trigger(EditBoard(Edit::DeleteTile { tile, marbles }));
+            break;
         }
     }
 }
 
+/// Why a tile can't be placed at an extent, if it can't.
+///
+/// `existing` holds the extent and kind of each tile on the board, and whether
+/// it is locked.
+fn placement_problem(
+    tile: Tile,
+    extent: &GridExtent,
+    existing: impl IntoIterator<Item = (GridExtent, Tile, bool)>,
+    puzzle: Option<&ActivePuzzle>,
+) -> Option<String> {
+    let mut placed = Vec::new();
+    for (existing_extent, existing_tile, locked) in existing {
+        // Check if the new tile collides with any existing tiles.
+        if existing_extent.intersects(extent) {
+            return Some("can't place tile due to collision".into());
+        }
+        if !locked {
+            placed.push(existing_tile);
+        }
+    }
+
+    // In a puzzle, the player may only place the tiles in the inventory.
+    if inventory_exhausted(puzzle, tile, placed) {
+        return Some(format!(
+            "no {} tiles left in the puzzle inventory",
+            tile.name()
+        ));
+    }
+    None
+}
+
+/// The tiles on the board, in the form [`placement_problem`] takes.
+fn existing(
+    tiles: &Query<(&GridExtent, &Tile, Has<Locked>), Without<GhostTile>>,
+) -> impl Iterator<Item = (GridExtent, Tile, bool)> {
+    tiles
+        .iter()
+        .map(|(&extent, &tile, locked)| (extent, tile, locked))
+}
+
 pub fn mouseclick_place_tile(
     mut event_reader: EventReader<MouseClick>,
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
     ghost: Query<(&Sprite, &Tile, &Offset), With<GhostTile>>,
-
-    existing_tiles: Query<&GridExtent, (With<Tile>, Without<GhostTile>)>,
+    existing_tiles: Query<(&GridExtent, &Tile, Has<Locked>), Without<GhostTile>>,
+    puzzle: Option<Res<ActivePuzzle>>,
 ) {
     for mouse_click in event_reader.read() {
         // Compute the world position of the new sprite.
         let (ghost_sprite, &tile, &offset) = ghost.single_inner().unwrap();
         let grid_position = GridPosition::from_world_with_offset(mouse_click.world_pos, offset);
-        let position = grid_position.to_world();
 
         // Compute the extent of the tile (its width in grid coordinates)
         let new_tile_extent = tile.extent(grid_position);
 
-        // Check if the new tile collides with any existing tiles.
-        for existing_extent in existing_tiles {
-            if existing_extent.intersects(&new_tile_extent) {
-                debug!("can't place tile due to collision");
-                return;
-            }
+        if let Some(problem) = placement_problem(
+            tile,
+            &new_tile_extent,
+            existing(&existing_tiles),
+            puzzle.as_deref(),
+        ) {
+            info!("{problem}");
+            return;
         }
 
         info!("spawn {tile:?}");
 
-        // why -1.0 ?
-        let position: Vec3 = (position, -1.0).into();
+        let tile = SimTile {
+            tile,
+            extent: new_tile_extent,
+            flip_x: ghost_sprite.flip_x,
+            flip_y: ghost_sprite.flip_y,
+            state: tile.initial_state(),
+        };
+        commands.trigger(EditBoard(Edit::PlaceTile {
+            tile,
+            marbles: Vec::new(),
+        }));
+    }
+}
+
+/// Spawn a tile entity, along with its marble sockets.
+pub fn spawn_tile(commands: &mut Commands, asset_server: &AssetServer, placed: SimTile) {
+    let SimTile {
+        tile,
+        extent,
+        flip_x,
+        flip_y,
+        state,
+    } = placed;
 
-        let mut sprite = tile.load_sprite(&asset_server);
-        sprite.flip_x = ghost_sprite.flip_x;
-        sprite.flip_y = ghost_sprite.flip_y;
-        commands.spawn((
+    // why -1.0 ?
+    let position: Vec3 = (extent.origin().to_world(), -1.0).into();
+
+    let mut sprite = tile.load_sprite(asset_server);
+    sprite.flip_x = flip_x;
+    sprite.flip_y = flip_y;
+    let tile_entity = commands
+        .spawn((
             sprite,
             Transform::from_translation(position),
             tile,
-            new_tile_extent,
-        ));
+            state,
+            extent,
+        ))
+        .with_child(state_indicator())
+        .id();
 
-        place_marble_sockets(
-            &mut commands,
-            &asset_server,
-            tile,
-            new_tile_extent,
-            ghost_sprite.flip_x,
-            ghost_sprite.flip_y,
-        );
-    }
+    place_marble_sockets(commands, asset_server, tile_entity, placed);
 }
 
 #[derive(Component)]
@@ -151,9 +307,6 @@ pub fn tile_placement_cursor_moved(
 
         ghost_transform.translation = ghost_pos;
 
-        // TODO: draw an outline showing the grid position,
-        // in the shape of the tile to be placed.
-
         //info!("New cursor position {cursor}, world coords {world_pos}, grid pos {grid_pos}");
     }
 }
@@ -171,7 +324,7 @@ pub fn spawn_ghost_tile(
     let mut sprite = tile.load_sprite(&asset_server);
     let offset = tile.offset();
     // translucent tile to differentiate it from the already-placed tiles.
-    sprite.color = Color::linear_rgba(1.0, 1.0, 1.0, 0.3);
+    sprite.color = GHOST_LEGAL_TINT;
     commands.spawn((
         sprite,
         // FIXME: the transform should be at the pointer location...
@@ -184,6 +337,40 @@ pub fn spawn_ghost_tile(
     next_state.set(SimState::Placing);
 }
 
+/// Tint the ghost tile by whether it can be placed where it is, and outline
+/// the grid cells it would cover.
+fn preview_ghost_tile(
+    mut ghost: Query<(&Transform, &Tile, &mut Sprite), With<GhostTile>>,
+    existing_tiles: Query<(&GridExtent, &Tile, Has<Locked>), Without<GhostTile>>,
+    puzzle: Option<Res<ActivePuzzle>>,
+    mut gizmos: Gizmos,
+) {
+    for (transform, &tile, mut sprite) in &mut ghost {
+        // The ghost always sits exactly on a grid position.
+        let extent = tile.extent(GridPosition::from_world(transform.translation.truncate()));
+        let legal = placement_problem(tile, &extent, existing(&existing_tiles), puzzle.as_deref())
+            .is_none();
+        let (tint, outline) = if legal {
+            (GHOST_LEGAL_TINT, GHOST_LEGAL_OUTLINE)
+        } else {
+            (GHOST_BLOCKED_TINT, GHOST_BLOCKED_OUTLINE)
+        };
+        if sprite.color != tint {
+            sprite.color = tint;
+        }
+        let rect = extent.world_rect();
+        let cells = UVec2::new(extent.width() as u32, GRID_UNITS_PER_TILE as u32);
+        gizmos
+            .grid_2d(
+                rect.center(),
+                cells,
+                Vec2::splat(PIXELS_PER_GRID_UNIT as f32),
+                outline,
+            )
+            .outer_edges();
+    }
+}
+
 #[derive(Event)]
 pub struct DespawnGhostTile;
 
@@ -197,3 +384,48 @@ pub fn despawn_ghost_tile(
         commands.entity(ghost_entity).despawn();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bevy::math::ivec2;
+
+    use super::*;
+    use crate::puzzle::{Goal, Puzzle};
+
+    fn at(tile: Tile, x: i32) -> (GridExtent, Tile, bool) {
+        (tile.extent(GridPosition(ivec2(x, 0))), tile, false)
+    }
+
+    #[test]
+    fn collisions_and_inventory() {
+        let path = Tile::Path.extent(GridPosition(ivec2(4, 0)));
+        assert_eq!(placement_problem(Tile::Path, &path, [], None), None);
+        assert_eq!(
+            placement_problem(Tile::Path, &path, [at(Tile::Path, 8)], None),
+            None
+        );
+        assert!(placement_problem(Tile::Path, &path, [at(Tile::Switch, 0)], None).is_some());
+
+        let puzzle = ActivePuzzle(Puzzle {
+            name: "test".into(),
+            description: String::new(),
+            locked: Vec::new(),
+            inventory: HashMap::from([(Tile::Path, 1)]),
+            marbles: Vec::new(),
+            goal: Goal::ExitOrder(Vec::new()),
+        });
+        let puzzle = Some(&puzzle);
+        assert_eq!(placement_problem(Tile::Path, &path, [], puzzle), None);
+        assert!(placement_problem(Tile::Turn, &path, [], puzzle).is_some());
+        // Locked tiles don't use up the inventory.
+        let locked = (
+            Tile::Path.extent(GridPosition(ivec2(-8, 0))),
+            Tile::Path,
+            true,
+        );
+        assert_eq!(placement_problem(Tile::Path, &path, [locked], puzzle), None);
+        assert!(placement_problem(Tile::Path, &path, [at(Tile::Path, -8)], puzzle).is_some());
+    }
+}
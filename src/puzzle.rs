@@ -0,0 +1,501 @@
+//! Puzzles: boards with locked tiles, a limited tile inventory, and a goal.
+//!
+//! Puzzles are written by hand, in a RON format that shares its tile entries
+//! with the board format:
+//!
+//! ```text
+//! (
+//!     version: 1,
+//!     name: "Divert",
+//!     description: "Place a switch so that the second marble leaves by the middle exit.",
+//!     locked: [
+//!         (kind: "path", origin: (0, 0)),
+//!         (kind: "path", origin: (4, 0)),
+//!     ],
+//!     inventory: {"switch": 1},
+//!     marbles: [(2, 3), (6, 1)],
+//!     goal: ExitOrder([(2, 7), (4, 7)]),
+//! )
+//! ```
+//!
+//! Marbles are written as in board files, so they can be given colours, and
+//! a `ColorExits` goal can send each colour to its own exit.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use bevy::math::ivec2;
+use serde::{Deserialize, Serialize};
+
+use crate::board::{
+    Board, BoardError, FORMAT_VERSION, MarbleEntry, PlacedMarble, TileEntry, parse_marbles,
+    parse_tiles,
+};
+use crate::grid::GridPosition;
+use crate::sim::{SimEvent, SimTile};
+use crate::tile::{MarbleColor, Tile, TileState};
+
+/// The most ticks a puzzle solution may take before it counts as a failure.
+pub const MAX_PUZZLE_TICKS: u32 = 100_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Puzzle {
+    pub name: String,
+    pub description: String,
+    /// Tiles that are already on the board, and can't be moved or removed.
+    pub locked: Vec<SimTile>,
+    /// How many of each kind of tile the player may place.
+    pub inventory: HashMap<Tile, u32>,
+    /// The marbles on the board at the start. The player can't change these.
+    pub marbles: Vec<PlacedMarble>,
+    pub goal: Goal,
+}
+
+/// What a solution must achieve once every marble has stopped.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Goal {
+    /// Marbles must leave the board at these positions, in this order.
+    ExitOrder(Vec<GridPosition>),
+    /// The tiles with these origins must end up in these states.
+    TileStates(Vec<(GridPosition, TileState)>),
+    /// Every marble of each of these colours must leave the board at the given position.
+    ColorExits(Vec<(MarbleColor, GridPosition)>),
+}
+
+/// Why a board doesn't solve a puzzle.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Failure {
+    /// A locked tile was removed or changed.
+    LockedTileMissing(Tile, GridPosition),
+    /// More tiles of this kind were placed than the inventory allows.
+    OverInventory {
+        tile: Tile,
+        placed: u32,
+        allowed: u32,
+    },
+    /// A placed tile didn't start in its initial state.
+    StartingState {
+        tile: Tile,
+        origin: GridPosition,
+        state: TileState,
+    },
+    /// The marbles aren't the puzzle's starting marbles.
+    MarblesChanged,
+    /// The marbles were still moving after [`MAX_PUZZLE_TICKS`].
+    DidNotFinish,
+    WrongExits {
+        expected: Vec<GridPosition>,
+        actual: Vec<GridPosition>,
+    },
+    WrongState {
+        origin: GridPosition,
+        expected: TileState,
+        actual: Option<TileState>,
+    },
+    /// A marble didn't leave by its colour's exit.
+    WrongColorExit {
+        id: u32,
+        color: MarbleColor,
+        expected: GridPosition,
+        actual: Option<GridPosition>,
+    },
+}
+
+impl Puzzle {
+    /// The board the player starts from.
+    pub fn board(&self) -> Board {
+        Board {
+            tiles: self.locked.clone(),
+            marbles: self.marbles.clone(),
+        }
+    }
+
+    /// Check whether a tile is one of the puzzle's locked tiles.
+    pub fn is_locked(&self, sim_tile: &SimTile) -> bool {
+        self.locked
+            .iter()
+            .any(|locked| same_placement(locked, sim_tile))
+    }
+
+    /// How many tiles of this kind the player may place.
+    pub fn allowance(&self, tile: Tile) -> u32 {
+        self.inventory.get(&tile).copied().unwrap_or(0)
+    }
+
+    /// Run a board and check that it solves the puzzle.
+    pub fn verify(&self, board: &Board) -> Result<(), Failure> {
+        for locked in &self.locked {
+            if !board.tiles.iter().any(|tile| same_placement(locked, tile)) {
+                return Err(Failure::LockedTileMissing(
+                    locked.tile,
+                    locked.extent.origin(),
+                ));
+            }
+        }
+
+        let mut placed: HashMap<Tile, u32> = HashMap::new();
+        for sim_tile in board.tiles.iter().filter(|tile| !self.is_locked(tile)) {
+            // A real machine's tiles start out in their initial states.
+            if sim_tile.state != sim_tile.tile.initial_state() {
+                return Err(Failure::StartingState {
+                    tile: sim_tile.tile,
+                    origin: sim_tile.extent.origin(),
+                    state: sim_tile.state,
+                });
+            }
+            *placed.entry(sim_tile.tile).or_default() += 1;
+        }
+        for (&tile, &placed) in &placed {
+            let allowed = self.allowance(tile);
+            if placed > allowed {
+                return Err(Failure::OverInventory {
+                    tile,
+                    placed,
+                    allowed,
+                });
+            }
+        }
+
+        // Ids don't matter, but positions and colours do.
+        let sorted = |marbles: &[PlacedMarble]| {
+            let mut marbles: Vec<(i32, i32, MarbleColor)> = marbles
+                .iter()
+                .map(|placed| {
+                    let GridPosition(pos) = placed.position;
+                    (pos.x, pos.y, placed.marble.color)
+                })
+                .collect();
+            marbles.sort_by_key(|&(x, y, _)| (x, y));
+            marbles
+        };
+        if sorted(&board.marbles) != sorted(&self.marbles) {
+            return Err(Failure::MarblesChanged);
+        }
+
+        let mut sim = board.simulation();
+        let events = sim.run(MAX_PUZZLE_TICKS).ok_or(Failure::DidNotFinish)?;
+
+        match &self.goal {
+            Goal::ExitOrder(expected) => {
+                let actual: Vec<GridPosition> = events
+                    .iter()
+                    .filter_map(|event| match *event {
+                        SimEvent::Exited { position, .. } => Some(position),
+                        _ => None,
+                    })
+                    .collect();
+                if &actual != expected {
+                    return Err(Failure::WrongExits {
+                        expected: expected.clone(),
+                        actual,
+                    });
+                }
+            }
+            Goal::TileStates(expected) => {
+                for &(origin, expected) in expected {
+                    let actual = sim
+                        .tiles()
+                        .iter()
+                        .find(|tile| tile.extent.origin() == origin)
+                        .map(|tile| tile.state);
+                    if actual != Some(expected) {
+                        return Err(Failure::WrongState {
+                            origin,
+                            expected,
+                            actual,
+                        });
+                    }
+                }
+            }
+            Goal::ColorExits(exits) => {
+                for (index, placed) in board.marbles.iter().enumerate() {
+                    let color = placed.marble.color;
+                    let Some(&(_, expected)) = exits.iter().find(|&&(c, _)| c == color) else {
+                        continue;
+                    };
+                    let actual = events.iter().find_map(|event| match *event {
+                        SimEvent::Exited { marble, position } if marble == index => Some(position),
+                        _ => None,
+                    });
+                    if actual != Some(expected) {
+                        return Err(Failure::WrongColorExit {
+                            id: placed.marble.id,
+                            color,
+                            expected,
+                            actual,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse a puzzle from the text file format.
+    pub fn from_ron(text: &str) -> Result<Self, BoardError> {
+        let file: PuzzleFile = ron::from_str(text).map_err(BoardError::Parse)?;
+        if file.version > FORMAT_VERSION {
+            return Err(BoardError::UnsupportedVersion(file.version));
+        }
+
+        let locked = parse_tiles(file.locked)?;
+        let mut inventory = HashMap::new();
+        for (name, count) in file.inventory {
+            let tile = Tile::from_name(&name).ok_or(BoardError::UnknownTile(name))?;
+            inventory.insert(tile, count);
+        }
+        let marbles = parse_marbles(file.marbles)?;
+        let goal = match file.goal {
+            GoalEntry::ExitOrder(exits) => {
+                Goal::ExitOrder(exits.into_iter().map(grid_position).collect())
+            }
+            GoalEntry::TileStates(states) => Goal::TileStates(
+                states
+                    .into_iter()
+                    .map(|entry| (grid_position(entry.origin), entry.state))
+                    .collect(),
+            ),
+            GoalEntry::ColorExits(exits) => Goal::ColorExits(
+                exits
+                    .into_iter()
+                    .map(|(name, position)| {
+                        let color =
+                            MarbleColor::from_name(&name).ok_or(BoardError::UnknownColor(name))?;
+                        Ok((color, grid_position(position)))
+                    })
+                    .collect::<Result<_, BoardError>>()?,
+            ),
+        };
+
+        Ok(Self {
+            name: file.name,
+            description: file.description,
+            locked,
+            inventory,
+            marbles,
+            goal,
+        })
+    }
+}
+
+/// Check that two tiles are the same kind, in the same place, with the same flips.
+fn same_placement(a: &SimTile, b: &SimTile) -> bool {
+    a.tile == b.tile && a.extent == b.extent && a.flip_x == b.flip_x && a.flip_y == b.flip_y
+}
+
+fn grid_position((x, y): (i32, i32)) -> GridPosition {
+    GridPosition(ivec2(x, y))
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::LockedTileMissing(tile, origin) => {
+                write!(f, "the locked {} at {origin} is missing", tile.name())
+            }
+            Failure::OverInventory {
+                tile,
+                placed,
+                allowed,
+            } => write!(
+                f,
+                "placed {placed} {} tiles, but only {allowed} are allowed",
+                tile.name()
+            ),
+            Failure::StartingState {
+                tile,
+                origin,
+                state,
+            } => write!(
+                f,
+                "the {} at {origin} started with {state}, not its initial state",
+                tile.name()
+            ),
+            Failure::MarblesChanged => write!(f, "the starting marbles were changed"),
+            Failure::DidNotFinish => write!(f, "the marbles never stopped moving"),
+            Failure::WrongExits { expected, actual } => {
+                write!(f, "marbles exited at {}", format_positions(actual))?;
+                write!(f, ", expected {}", format_positions(expected))
+            }
+            Failure::WrongState {
+                origin,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "tile at {origin} ended in state {actual:?}, expected {expected:?}"
+            ),
+            Failure::WrongColorExit {
+                id,
+                color,
+                expected,
+                actual: Some(actual),
+            } => write!(
+                f,
+                "{} marble {id} exited at {actual}, expected {expected}",
+                color.name()
+            ),
+            Failure::WrongColorExit {
+                id,
+                color,
+                expected,
+                actual: None,
+            } => write!(
+                f,
+                "{} marble {id} never exited, expected {expected}",
+                color.name()
+            ),
+        }
+    }
+}
+
+fn format_positions(positions: &[GridPosition]) -> String {
+    let positions: Vec<String> = positions.iter().map(ToString::to_string).collect();
+    format!("[{}]", positions.join(", "))
+}
+
+#[derive(Serialize, Deserialize)]
+struct PuzzleFile {
+    version: u32,
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    locked: Vec<TileEntry>,
+    #[serde(default)]
+    inventory: HashMap<String, u32>,
+    #[serde(default)]
+    marbles: Vec<MarbleEntry>,
+    goal: GoalEntry,
+}
+
+#[derive(Serialize, Deserialize)]
+enum GoalEntry {
+    ExitOrder(Vec<(i32, i32)>),
+    TileStates(Vec<TileStateEntry>),
+    ColorExits(Vec<(String, (i32, i32))>),
+}
+
+#[derive(Serialize, Deserialize)]
+struct TileStateEntry {
+    origin: (i32, i32),
+    state: TileState,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two marbles roll up through locked paths into a switch that the player places.
+    /// The first one flips the lever, so the second one leaves by the middle exit.
+    const DIVERT: &str = include_str!("../puzzles/divert.ron");
+
+    fn switch_at(x: i32, y: i32) -> SimTile {
+        let tile = Tile::Switch;
+        SimTile {
+            tile,
+            extent: tile.extent(grid_position((x, y))),
+            flip_x: false,
+            flip_y: false,
+            state: tile.initial_state(),
+        }
+    }
+
+    #[test]
+    fn verify_solution() {
+        let puzzle = Puzzle::from_ron(DIVERT).unwrap();
+        assert_eq!(puzzle.allowance(Tile::Switch), 1);
+        assert_eq!(puzzle.allowance(Tile::Trap), 0);
+
+        // The unsolved board doesn't route anything into the switch lane.
+        let mut board = puzzle.board();
+        assert!(matches!(
+            puzzle.verify(&board),
+            Err(Failure::WrongExits { .. })
+        ));
+
+        board.tiles.push(switch_at(0, 4));
+        assert_eq!(puzzle.verify(&board), Ok(()));
+
+        // A switch that starts out diverted would solve it differently.
+        let mut diverted = board.clone();
+        diverted.tiles.last_mut().unwrap().state = TileState::Lever { diverted: true };
+        assert!(matches!(
+            puzzle.verify(&diverted),
+            Err(Failure::StartingState {
+                tile: Tile::Switch,
+                ..
+            })
+        ));
+
+        board.tiles.push(switch_at(0, 8));
+        assert!(matches!(
+            puzzle.verify(&board),
+            Err(Failure::OverInventory {
+                tile: Tile::Switch,
+                placed: 2,
+                allowed: 1
+            })
+        ));
+
+        let mut board = puzzle.board();
+        board.tiles.remove(0);
+        assert!(matches!(
+            puzzle.verify(&board),
+            Err(Failure::LockedTileMissing(Tile::Path, _))
+        ));
+    }
+
+    #[test]
+    fn tile_state_goal() {
+        let text = r#"(
+            version: 1,
+            name: "Flip",
+            inventory: {"switch": 1},
+            marbles: [],
+            goal: TileStates([(origin: (0, 0), state: Lever(diverted: false))]),
+        )"#;
+        let puzzle = Puzzle::from_ron(text).unwrap();
+        let mut board = puzzle.board();
+        assert!(matches!(
+            puzzle.verify(&board),
+            Err(Failure::WrongState { actual: None, .. })
+        ));
+        board.tiles.push(switch_at(0, 0));
+        assert_eq!(puzzle.verify(&board), Ok(()));
+    }
+
+    #[test]
+    fn color_exit_goal() {
+        // The same machine as DIVERT: the red marble flips the lever, so the
+        // blue one leaves by the middle exit.
+        let text = r#"(
+            version: 2,
+            name: "Sort",
+            locked: [(kind: "path", origin: (0, 0)), (kind: "path", origin: (4, 0))],
+            inventory: {"switch": 1},
+            marbles: [
+                (color: "red", position: (2, 3)),
+                (color: "blue", position: (6, 1)),
+            ],
+            goal: ColorExits([("red", (2, 7)), ("blue", (4, 7))]),
+        )"#;
+        let puzzle = Puzzle::from_ron(text).unwrap();
+        let mut board = puzzle.board();
+        assert!(matches!(
+            puzzle.verify(&board),
+            Err(Failure::WrongColorExit {
+                color: MarbleColor::Red,
+                ..
+            })
+        ));
+
+        board.tiles.push(switch_at(0, 4));
+        assert_eq!(puzzle.verify(&board), Ok(()));
+
+        // Swapping the colours is a different set of starting marbles.
+        board.marbles[0].marble.color = MarbleColor::Blue;
+        board.marbles[1].marble.color = MarbleColor::Red;
+        assert_eq!(puzzle.verify(&board), Err(Failure::MarblesChanged));
+    }
+}
@@ -0,0 +1,221 @@
+//! Playing puzzles in the editor.
+//!
+//! A puzzle is loaded with `--puzzle=<file>`. Its locked tiles can't be
+//! deleted or flipped, tile placement is limited by the inventory, and the
+//! starting marbles can't be changed. Starting a run, with Play or a step,
+//! checks the board the run starts from against the puzzle's goal, and
+//! reports the result once the marbles stop, with the reason for a failure.
+
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::{
+    SimState,
+    history::History,
+    place_tile::DespawnGhostTile,
+    puzzle::{Failure, Puzzle},
+    save_load::{BoardEntity, load_board_at_startup, spawn_board},
+    sim::SimTile,
+    simulate::{ActiveSimulation, Snapshot, discard_simulation},
+    tile::{GridExtent, Tile, TileState},
+    ui::{UiPuzzleText, UiTileCaption},
+};
+
+/// How locked tiles are tinted, to tell them apart from the player's tiles.
+const LOCKED_TINT: Color = Color::srgb(0.7, 0.7, 0.8);
+
+pub struct PuzzlePlugin;
+
+impl Plugin for PuzzlePlugin {
+    fn build(&self, app: &mut App) {
+        let path =
+            std::env::args().find_map(|arg| arg.strip_prefix("--puzzle=").map(PathBuf::from));
+
+        app.add_observer(load_puzzle)
+            .add_systems(
+                Update,
+                (
+                    mark_locked_tiles,
+                    show_inventory,
+                    verify_new_run.run_if(resource_added::<Snapshot>),
+                    report_verdict,
+                )
+                    .chain()
+                    .run_if(resource_exists::<ActivePuzzle>),
+            )
+            .add_systems(OnEnter(SimState::Idle), forget_verdict);
+        if let Some(path) = path {
+            // Load the puzzle first, so that a board given on the command line
+            // can be loaded on top of it as a saved solution.
+            app.add_systems(
+                Startup,
+                (move |mut commands: Commands| {
+                    commands.trigger(LoadPuzzle(path.clone()));
+                })
+                .before(load_board_at_startup),
+            );
+        }
+    }
+}
+
+/// The puzzle being played.
+#[derive(Resource)]
+pub struct ActivePuzzle(pub Puzzle);
+
+/// Marks a tile that belongs to the puzzle, and can't be changed.
+#[derive(Component)]
+pub struct Locked;
+
+/// The result of checking the board when Play was pressed, waiting to be shown
+/// until the marbles stop.
+#[derive(Resource)]
+struct PendingVerdict(Result<(), Failure>);
+
+/// Replace the board with a puzzle's starting board.
+#[derive(Event)]
+pub struct LoadPuzzle(pub PathBuf);
+
+fn load_puzzle(
+    trigger: Trigger<LoadPuzzle>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    board_entities: Query<Entity, BoardEntity>,
+    mut history: ResMut<History>,
+    mut next_state: ResMut<NextState<SimState>>,
+    mut text: Query<&mut Text, With<UiPuzzleText>>,
+) {
+    let LoadPuzzle(path) = trigger.event();
+    let puzzle = match std::fs::read_to_string(path) {
+        Ok(text) => Puzzle::from_ron(&text),
+        Err(e) => {
+            error!("failed to read {}: {e}", path.display());
+            return;
+        }
+    };
+    let puzzle = match puzzle {
+        Ok(puzzle) => puzzle,
+        Err(e) => {
+            error!("failed to load puzzle {}: {e}", path.display());
+            return;
+        }
+    };
+
+    info!("puzzle \"{}\": {}", puzzle.name, puzzle.description);
+    discard_simulation(&mut commands);
+    commands.trigger(DespawnGhostTile);
+    for entity in &board_entities {
+        commands.entity(entity).despawn();
+    }
+    spawn_board(&mut commands, &asset_server, &puzzle.board());
+    history.clear();
+    for mut text in &mut text {
+        text.0 = puzzle.name.clone();
+    }
+    commands.insert_resource(ActivePuzzle(puzzle));
+    next_state.set(SimState::Idle);
+}
+
+/// Lock and tint newly spawned tiles that belong to the puzzle.
+fn mark_locked_tiles(
+    puzzle: Res<ActivePuzzle>,
+    mut commands: Commands,
+    mut tiles: Query<(Entity, &Tile, &TileState, &GridExtent, &mut Sprite), Added<GridExtent>>,
+) {
+    for (entity, &tile, &state, &extent, mut sprite) in &mut tiles {
+        let sim_tile = SimTile {
+            tile,
+            extent,
+            flip_x: sprite.flip_x,
+            flip_y: sprite.flip_y,
+            state,
+        };
+        if puzzle.0.is_locked(&sim_tile) {
+            commands.entity(entity).insert(Locked);
+            sprite.color = LOCKED_TINT;
+        }
+    }
+}
+
+/// Show how many of each tile the player has left to place.
+fn show_inventory(
+    puzzle: Res<ActivePuzzle>,
+    placed: Query<&Tile, (With<GridExtent>, Without<Locked>)>,
+    mut captions: Query<(&mut Text, &UiTileCaption)>,
+) {
+    for (mut text, &UiTileCaption(tile)) in &mut captions {
+        let used = placed.iter().filter(|&&placed| placed == tile).count() as u32;
+        let remaining = puzzle.0.allowance(tile).saturating_sub(used);
+        let caption = format!("{} {remaining}", tile.name());
+        if text.0 != caption {
+            text.0 = caption;
+        }
+    }
+}
+
+/// Check whether the tiles the player may place of this kind have run out.
+pub fn inventory_exhausted(
+    puzzle: Option<&ActivePuzzle>,
+    tile: Tile,
+    placed: impl IntoIterator<Item = Tile>,
+) -> bool {
+    let Some(ActivePuzzle(puzzle)) = puzzle else {
+        return false;
+    };
+    let used = placed.into_iter().filter(|&placed| placed == tile).count() as u32;
+    used >= puzzle.allowance(tile)
+}
+
+/// Check the board against the puzzle goal when a new run starts.
+///
+/// A snapshot is taken whenever a run starts, but not when a paused run is
+/// resumed, and it holds the board as the run started.
+fn verify_new_run(
+    mut commands: Commands,
+    puzzle: Res<ActivePuzzle>,
+    snapshot: Res<Snapshot>,
+    mut text: Query<&mut Text, With<UiPuzzleText>>,
+) {
+    let verdict = puzzle.0.verify(&snapshot.0);
+    for mut text in &mut text {
+        text.0 = format!("{}: running", puzzle.0.name);
+    }
+    commands.insert_resource(PendingVerdict(verdict));
+}
+
+/// Show the verdict once the simulation has finished.
+fn report_verdict(
+    mut commands: Commands,
+    puzzle: Res<ActivePuzzle>,
+    verdict: Option<Res<PendingVerdict>>,
+    active: Option<Res<ActiveSimulation>>,
+    mut text: Query<&mut Text, With<UiPuzzleText>>,
+) {
+    let Some(verdict) = verdict else {
+        return;
+    };
+    // A run that never stops can be reported straight away.
+    let never_stops = verdict.0 == Err(Failure::DidNotFinish);
+    if !never_stops && !active.is_some_and(|active| active.is_finished()) {
+        return;
+    }
+    let message = match &verdict.0 {
+        Ok(()) => {
+            info!("puzzle \"{}\" solved", puzzle.0.name);
+            format!("{}: pass", puzzle.0.name)
+        }
+        Err(failure) => {
+            info!("puzzle \"{}\" failed: {failure}", puzzle.0.name);
+            format!("{}: fail: {failure}", puzzle.0.name)
+        }
+    };
+    for mut text in &mut text {
+        text.0 = message.clone();
+    }
+    commands.remove_resource::<PendingVerdict>();
+}
+
+/// Drop an unreported verdict when the run is abandoned.
+fn forget_verdict(mut commands: Commands) {
+    commands.remove_resource::<PendingVerdict>();
+}
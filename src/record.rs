@@ -0,0 +1,271 @@
+//! Animated recordings of a run, for bug reports and puzzle write-ups.
+//!
+//! The board is simulated from its starting position until every marble has
+//! stopped, and each tick becomes a frame. Frames are drawn in software with
+//! the same sprites as exported PNGs, so recording doesn't need a GPU. The
+//! animation is written as a GIF or an APNG, and loops forever.
+
+use std::path::Path;
+
+use image::{Delay, Frame, RgbaImage, codecs::gif};
+
+use crate::{
+    board::{Board, PlacedMarble},
+    export::{Bounds, ExportError, Sprites, draw_board},
+    sim::Phase,
+};
+
+/// How long the last frame stays up before the animation starts again, in milliseconds.
+const FINAL_FRAME_MS: u32 = 1000;
+
+/// How hard the GIF encoder works to pick colours, from 1 (slowest) to 30.
+const GIF_SPEED: i32 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    /// Pick a format from a file name: `.gif` for GIF, and `.png` or `.apng` for APNG.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gif" => Some(AnimationFormat::Gif),
+            "png" | "apng" => Some(AnimationFormat::Apng),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "gif",
+            AnimationFormat::Apng => "apng",
+        }
+    }
+}
+
+/// How to record a run.
+#[derive(Clone, Copy, Debug)]
+pub struct RecordOptions {
+    /// How many image pixels each world pixel becomes.
+    pub scale: u32,
+    /// How many ticks are shown each second.
+    pub ticks_per_second: u32,
+    /// Stop recording after this many ticks, even if marbles are still moving.
+    pub max_ticks: u32,
+}
+
+impl Default for RecordOptions {
+    fn default() -> Self {
+        Self {
+            scale: 4,
+            ticks_per_second: 4,
+            max_ticks: 1000,
+        }
+    }
+}
+
+/// The board before each tick of its run, and after the last one.
+///
+/// Marbles that have left the board are left out.
+pub fn run_frames(board: &Board, max_ticks: u32) -> Vec<Board> {
+    let mut sim = board.simulation();
+    let mut frames = vec![board.clone()];
+    for _ in 0..max_ticks {
+        if sim.is_quiescent() {
+            break;
+        }
+        sim.tick();
+        let marbles = board
+            .marbles
+            .iter()
+            .zip(sim.marbles())
+            .filter(|(_, sim_marble)| sim_marble.phase != Phase::Exited)
+            .map(|(placed, sim_marble)| PlacedMarble {
+                position: sim_marble.position,
+                marble: placed.marble,
+            })
+            .collect();
+        frames.push(Board {
+            tiles: sim.tiles().to_vec(),
+            marbles,
+        });
+    }
+    frames
+}
+
+/// Record a run of a board as an animation file.
+pub fn record(
+    board: &Board,
+    sprites: &Sprites,
+    format: AnimationFormat,
+    options: RecordOptions,
+) -> Result<Vec<u8>, ExportError> {
+    if options.scale == 0 {
+        return Err(ExportError::ZeroScale);
+    }
+    if !(1..=1000).contains(&options.ticks_per_second) {
+        return Err(ExportError::TickRate(options.ticks_per_second));
+    }
+    let boards = run_frames(board, options.max_ticks);
+    // Every frame covers the same area, so the board doesn't jump about.
+    let bounds = Bounds::covering(&boards)?;
+    let frames = boards
+        .iter()
+        .map(|board| draw_board(board, sprites, options.scale, bounds));
+    match format {
+        AnimationFormat::Gif => encode_gif(frames, options.ticks_per_second),
+        AnimationFormat::Apng => {
+            let size = bounds.picture_size(options.scale);
+            encode_apng(frames, size.x, size.y, options.ticks_per_second)
+        }
+    }
+}
+
+/// Whether each frame is the last, alongside the frame.
+fn mark_last<T>(items: impl ExactSizeIterator<Item = T>) -> impl Iterator<Item = (bool, T)> {
+    let count = items.len();
+    items
+        .enumerate()
+        .map(move |(index, item)| (index + 1 == count, item))
+}
+
+fn encode_gif(
+    frames: impl ExactSizeIterator<Item = Result<RgbaImage, ExportError>>,
+    ticks_per_second: u32,
+) -> Result<Vec<u8>, ExportError> {
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = gif::GifEncoder::new_with_speed(&mut bytes, GIF_SPEED);
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(ExportError::Image)?;
+        for (last, image) in mark_last(frames) {
+            let delay = if last {
+                Delay::from_numer_denom_ms(FINAL_FRAME_MS, 1)
+            } else {
+                Delay::from_numer_denom_ms(1000, ticks_per_second)
+            };
+            encoder
+                .encode_frame(Frame::from_parts(image?, 0, 0, delay))
+                .map_err(ExportError::Image)?;
+        }
+    }
+    Ok(bytes)
+}
+
+fn encode_apng(
+    frames: impl ExactSizeIterator<Item = Result<RgbaImage, ExportError>>,
+    width: u32,
+    height: u32,
+    ticks_per_second: u32,
+) -> Result<Vec<u8>, ExportError> {
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(frames.len() as u32, 0)
+        .map_err(ExportError::Png)?;
+    let mut writer = encoder.write_header().map_err(ExportError::Png)?;
+    for (last, image) in mark_last(frames) {
+        let (numerator, denominator) = if last {
+            (FINAL_FRAME_MS as u16, 1000)
+        } else {
+            (1, ticks_per_second as u16)
+        };
+        writer
+            .set_frame_delay(numerator, denominator)
+            .map_err(ExportError::Png)?;
+        writer
+            .write_image_data(image?.as_raw())
+            .map_err(ExportError::Png)?;
+    }
+    writer.finish().map_err(ExportError::Png)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path with a marble at its input, which falls through and leaves the board.
+    fn falling_marble() -> Board {
+        Board::from_ron(
+            r#"(
+                version: 3,
+                tiles: [(kind: "path", origin: (0, 0))],
+                marbles: [(id: 0, color: "green", position: (2, 1))],
+            )"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn frames_follow_the_run() {
+        let board = falling_marble();
+        let frames = run_frames(&board, 100);
+        assert_eq!(frames[0], board);
+        assert!(frames.len() > 2);
+        assert!(frames[1].marbles[0].position != board.marbles[0].position);
+        // The marble has left by the last frame.
+        assert!(frames.last().unwrap().marbles.is_empty());
+        assert_eq!(run_frames(&board, 1).len(), 2);
+    }
+
+    #[test]
+    fn formats_from_paths() {
+        let format = |name: &str| AnimationFormat::from_path(Path::new(name));
+        assert_eq!(format("run.gif"), Some(AnimationFormat::Gif));
+        assert_eq!(format("run.APNG"), Some(AnimationFormat::Apng));
+        assert_eq!(format("run.png"), Some(AnimationFormat::Apng));
+        assert_eq!(format("run.ron"), None);
+    }
+
+    #[test]
+    fn animations_have_a_frame_per_tick() {
+        let board = falling_marble();
+        let frame_count = run_frames(&board, 100).len();
+        let options = RecordOptions {
+            scale: 2,
+            ..RecordOptions::default()
+        };
+
+        let gif = record(
+            &board,
+            &Sprites::repo_assets(),
+            AnimationFormat::Gif,
+            options,
+        )
+        .unwrap();
+        assert!(gif.starts_with(b"GIF89a"));
+
+        let apng = record(
+            &board,
+            &Sprites::repo_assets(),
+            AnimationFormat::Apng,
+            options,
+        )
+        .unwrap();
+        let reader = png::Decoder::new(apng.as_slice()).read_info().unwrap();
+        let control = reader.info().animation_control().unwrap();
+        assert_eq!(control.num_frames as usize, frame_count);
+        assert_eq!(control.num_plays, 0);
+
+        let stopped = RecordOptions {
+            ticks_per_second: 0,
+            ..options
+        };
+        assert!(matches!(
+            record(
+                &board,
+                &Sprites::repo_assets(),
+                AnimationFormat::Gif,
+                stopped
+            ),
+            Err(ExportError::TickRate(0))
+        ));
+    }
+}
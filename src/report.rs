@@ -0,0 +1,204 @@
+//! Running a board without the editor, and summarizing the outcome.
+
+use serde::Serialize;
+
+use crate::board::{Board, PlacedMarble};
+use crate::grid::GridPosition;
+use crate::sim::{Phase, SimEvent, SimMarble};
+use crate::tile::{MarbleColor, TileState};
+
+/// The outcome of running a board.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Report {
+    /// How many ticks were simulated.
+    pub ticks: u32,
+    /// Whether every marble had stopped when the run ended.
+    pub quiescent: bool,
+    /// Where each marble ended up, in the order they appear in the board file.
+    pub marbles: Vec<MarbleReport>,
+    /// The final state of each tile, in the order they appear in the board file.
+    pub tiles: Vec<TileReport>,
+    /// Every marble that left the board, in the order they left.
+    pub exits: Vec<ExitReport>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MarbleReport {
+    pub id: u32,
+    pub color: MarbleColor,
+    pub position: (i32, i32),
+    pub phase: Phase,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TileReport {
+    pub kind: &'static str,
+    pub origin: (i32, i32),
+    pub state: TileState,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ExitReport {
+    /// The tick during which the marble left, counting from 1.
+    pub tick: u32,
+    /// The id of the marble that left.
+    pub marble: u32,
+    pub color: MarbleColor,
+    pub position: (i32, i32),
+}
+
+impl Report {
+    /// Simulate a board until every marble stops, or for at most `max_ticks`.
+    pub fn run(board: &Board, max_ticks: u32) -> Self {
+        let mut sim = board.simulation();
+        let mut ticks = 0;
+        let mut exits = Vec::new();
+        while ticks < max_ticks && !sim.is_quiescent() {
+            ticks += 1;
+            for event in sim.tick() {
+                if let SimEvent::Exited { marble, position } = event {
+                    let placed = board.marbles[marble];
+                    exits.push(ExitReport {
+                        tick: ticks,
+                        marble: placed.marble.id,
+                        color: placed.marble.color,
+                        position: position.0.into(),
+                    });
+                }
+            }
+        }
+
+        Self {
+            ticks,
+            quiescent: sim.is_quiescent(),
+            marbles: sim
+                .marbles()
+                .iter()
+                .zip(&board.marbles)
+                .map(|(sim_marble, placed)| MarbleReport {
+                    id: placed.marble.id,
+                    color: placed.marble.color,
+                    position: sim_marble.position.0.into(),
+                    phase: sim_marble.phase,
+                })
+                .collect(),
+            tiles: sim
+                .tiles()
+                .iter()
+                .map(|sim_tile| {
+                    let GridPosition(origin) = sim_tile.extent.origin();
+                    TileReport {
+                        kind: sim_tile.tile.name(),
+                        origin: origin.into(),
+                        state: sim_tile.state,
+                    }
+                })
+                .collect(),
+            exits,
+        }
+    }
+
+    /// Format the report as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report serialization")
+    }
+}
+
+/// Simulate a board, describing the marbles after every tick and the final tile states.
+///
+/// The trace is plain text with one line per tick, so that changes in
+/// behaviour show up as readable diffs.
+pub fn trace(board: &Board, max_ticks: u32) -> String {
+    let mut sim = board.simulation();
+    let describe = |marbles: &[SimMarble]| describe_marbles(marbles, &board.marbles);
+    let mut lines = vec![format!("start: {}", describe(sim.marbles()))];
+    let mut ticks = 0;
+    while ticks < max_ticks && !sim.is_quiescent() {
+        ticks += 1;
+        sim.tick();
+        lines.push(format!("tick {ticks}: {}", describe(sim.marbles())));
+    }
+    if !sim.is_quiescent() {
+        lines.push(format!("still moving after {ticks} ticks"));
+    }
+    for (index, sim_tile) in sim.tiles().iter().enumerate() {
+        lines.push(format!(
+            "tile {index} {} at {}: {:?}",
+            sim_tile.tile.name(),
+            sim_tile.extent.origin(),
+            sim_tile.state
+        ));
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Describe each marble as "id colour position phase".
+fn describe_marbles(marbles: &[SimMarble], placed: &[PlacedMarble]) -> String {
+    let marbles: Vec<String> = marbles
+        .iter()
+        .zip(placed)
+        .map(|(sim_marble, placed)| {
+            format!(
+                "{} {} {} {}",
+                placed.marble.id,
+                placed.marble.color.name(),
+                sim_marble.position,
+                sim_marble.phase.name()
+            )
+        })
+        .collect();
+    marbles.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switch_report() {
+        let text = r#"(
+            version: 1,
+            tiles: [
+                (kind: "path", origin: (0, 0)),
+                (kind: "path", origin: (4, 0)),
+                (kind: "switch", origin: (0, 4)),
+            ],
+            marbles: [(2, 3), (6, 1)],
+        )"#;
+        let board = Board::from_ron(text).unwrap();
+        let report = Report::run(&board, 100);
+        assert!(report.quiescent);
+        assert_eq!(report.ticks, 4);
+        assert_eq!(
+            report.exits,
+            vec![
+                ExitReport {
+                    tick: 3,
+                    marble: 0,
+                    color: MarbleColor::White,
+                    position: (2, 7),
+                },
+                ExitReport {
+                    tick: 4,
+                    marble: 1,
+                    color: MarbleColor::White,
+                    position: (4, 7),
+                },
+            ]
+        );
+        assert_eq!(report.tiles[2].state, TileState::Lever { diverted: true });
+
+        let json = report.to_json();
+        assert!(json.contains(r#""phase": "exited""#));
+        assert!(json.contains(r#""color": "white""#));
+        assert!(json.contains(r#""Lever": {"#));
+
+        // Stopping early leaves the marbles in flight.
+        let partial = Report::run(&board, 2);
+        assert_eq!(partial.ticks, 2);
+        assert!(!partial.quiescent);
+        assert!(partial.exits.is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::{
+    SimState,
+    board::{Board, PlacedMarble},
+    grid::GridPosition,
+    history::History,
+    place_marble::spawn_marble,
+    place_tile::{DespawnGhostTile, spawn_tile},
+    sim::SimTile,
+    simulate::discard_simulation,
+    tile::{GridExtent, Marble, Tile, TileState},
+};
+
+/// The file used when no path is given on the command line.
+const DEFAULT_BOARD_FILE: &str = "board.ron";
+
+pub struct SaveLoadPlugin;
+
+impl Plugin for SaveLoadPlugin {
+    fn build(&self, app: &mut App) {
+        // The board file is the first argument that isn't an option.
+        let path = std::env::args().skip(1).find(|arg| !arg.starts_with("--"));
+        let load_at_startup = path.is_some();
+        let path = PathBuf::from(path.unwrap_or_else(|| DEFAULT_BOARD_FILE.into()));
+
+        app.insert_resource(BoardPath(path))
+            .add_systems(Update, save_load_keyboard)
+            .add_observer(save_board)
+            .add_observer(load_board);
+        if load_at_startup {
+            app.add_systems(Startup, load_board_at_startup);
+        }
+    }
+}
+
+pub fn load_board_at_startup(mut commands: Commands) {
+    commands.trigger(LoadBoard);
+}
+
+/// The file that boards are saved to and loaded from.
+#[derive(Resource)]
+pub struct BoardPath(pub PathBuf);
+
+#[derive(Event)]
+pub struct SaveBoard;
+
+#[derive(Event)]
+pub struct LoadBoard;
+
+/// Handle the Ctrl+S and Ctrl+O shortcuts.
+fn save_load_keyboard(keyboard: Res<ButtonInput<KeyCode>>, mut commands: Commands) {
+    if !keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    if keyboard.just_pressed(KeyCode::KeyS) {
+        commands.trigger(SaveBoard);
+    }
+    if keyboard.just_pressed(KeyCode::KeyO) {
+        commands.trigger(LoadBoard);
+    }
+}
+
+/// Entities that are part of the board: tiles and marbles.
+///
+/// Marble sockets are children of the tiles, so they are despawned along with them.
+pub type BoardEntity = Or<(With<GridExtent>, (With<Marble>, With<GridPosition>))>;
+
+/// Collect the tiles and marbles on the board.
+pub fn read_board(
+    tiles: &Query<(&Tile, &TileState, &GridExtent, &Sprite)>,
+    marbles: &Query<(&GridPosition, &Marble)>,
+) -> Board {
+    let tiles = tiles
+        .iter()
+        .map(|(&tile, &state, &extent, sprite)| SimTile {
+            tile,
+            extent,
+            flip_x: sprite.flip_x,
+            flip_y: sprite.flip_y,
+            state,
+        })
+        .collect();
+    let mut marbles: Vec<PlacedMarble> = marbles
+        .iter()
+        .map(|(&position, &marble)| PlacedMarble { position, marble })
+        .collect();
+    marbles.sort_by_key(|placed| placed.marble.id);
+    Board { tiles, marbles }
+}
+
+/// Spawn the entities for every tile and marble on a board.
+pub fn spawn_board(commands: &mut Commands, asset_server: &AssetServer, board: &Board) {
+    for &tile in &board.tiles {
+        spawn_tile(commands, asset_server, tile);
+    }
+    for &placed in &board.marbles {
+        spawn_marble(commands, asset_server, placed);
+    }
+}
+
+fn save_board(
+    _trigger: Trigger<SaveBoard>,
+    path: Res<BoardPath>,
+    tiles: Query<(&Tile, &TileState, &GridExtent, &Sprite)>,
+    marbles: Query<(&GridPosition, &Marble)>,
+) {
+    let board = read_board(&tiles, &marbles);
+    let path = &path.0;
+    match write_file(path, board.to_ron()) {
+        Ok(()) => info!("saved board to {}", path.display()),
+        Err(e) => error!("failed to save board to {}: {e}", path.display()),
+    }
+}
+
+/// Replace the board with the contents of the board file.
+fn load_board(
+    _trigger: Trigger<LoadBoard>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    path: Res<BoardPath>,
+    board_entities: Query<Entity, BoardEntity>,
+    mut history: ResMut<History>,
+    mut next_state: ResMut<NextState<SimState>>,
+) {
+    let path = &path.0;
+    let text = match read_file(path) {
+        Ok(text) => text,
+        Err(e) => {
+            error!("failed to read {}: {e}", path.display());
+            return;
+        }
+    };
+    let board = match Board::from_ron(&text) {
+        Ok(board) => board,
+        Err(e) => {
+            error!("failed to load {}: {e}", path.display());
+            return;
+        }
+    };
+
+    info!("loaded board from {}", path.display());
+    discard_simulation(&mut commands);
+    commands.trigger(DespawnGhostTile);
+    for entity in &board_entities {
+        commands.entity(entity).despawn();
+    }
+    spawn_board(&mut commands, &asset_server, &board);
+    history.clear();
+    next_state.set(SimState::Idle);
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn read_file(path: &std::path::Path) -> std::io::Result<String> {
+    std::fs::read_to_string(path)
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn write_file(path: &std::path::Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    std::fs::write(path, contents)
+}
+
+#[cfg(target_family = "wasm")]
+pub fn read_file(_path: &std::path::Path) -> std::io::Result<String> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(target_family = "wasm")]
+pub fn write_file(_path: &std::path::Path, _contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
@@ -0,0 +1,609 @@
+//! Selecting groups of tiles, and moving, duplicating, deleting or copying them.
+//!
+//! In the select tool, dragging a box selects the tiles it touches, along with
+//! the marbles sitting on them. Dragging a selected tile moves the whole
+//! group, and so do the arrow keys. Ctrl+D duplicates the group, Delete
+//! removes it, and Ctrl+C, Ctrl+X and Ctrl+V copy, cut and paste it through
+//! the clipboard as board text.
+//!
+//! Groups only move by whole rows and even numbers of grid units, so that
+//! every tile keeps its `Offset`.
+
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::{
+    MainCamera, SimState,
+    blueprint::BlueprintNamePrompt,
+    board::{Board, PlacedMarble, is_legal_shift},
+    grid::{GRID_UNITS_PER_TILE, GridPosition, PIXELS_PER_GRID_UNIT},
+    history::{Edit, EditBoard},
+    puzzle_mode::{ActivePuzzle, Locked, inventory_exhausted},
+    sim::SimTile,
+    tile::{GridExtent, Marble, Tile, TileState},
+};
+
+/// How selected tiles are tinted.
+const SELECTED_TINT: Color = Color::srgb(0.6, 0.8, 1.0);
+
+/// The colour of the selection box, and of the outlines of a group being moved.
+const SELECT_BOX_COLOR: Color = Color::srgb(0.4, 0.7, 1.0);
+
+/// How far the arrow keys move the selection, in grid units.
+const ARROW_STEP: IVec2 = IVec2::new(2, GRID_UNITS_PER_TILE);
+
+/// How many places to the right a duplicate may be shifted to find room.
+const MAX_DUPLICATE_TRIES: i32 = 32;
+
+pub struct SelectPlugin;
+
+impl Plugin for SelectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Selection>()
+            .init_resource::<Clipboard>()
+            .init_resource::<SelectDrag>()
+            .add_systems(
+                Update,
+                (
+                    select_mouse,
+                    select_keyboard.run_if(not(resource_exists::<BlueprintNamePrompt>)),
+                    draw_drag,
+                )
+                    .chain()
+                    .run_if(in_state(SimState::Selecting)),
+            )
+            .add_systems(Update, tint_selection)
+            .add_systems(OnExit(SimState::Selecting), clear_selection);
+    }
+}
+
+/// The origins of the selected tiles.
+///
+/// Tiles are tracked by origin rather than by entity, because moving or
+/// undoing respawns them.
+#[derive(Resource, Default)]
+pub struct Selection(pub Vec<GridPosition>);
+
+/// A mouse drag in progress.
+#[derive(Resource, Default)]
+enum SelectDrag {
+    #[default]
+    None,
+    /// Dragging out a selection box from this world position.
+    Box(Vec2),
+    /// Dragging the selection, having grabbed this tile at this world position.
+    Move { grab: Vec2, anchor: SimTile },
+}
+
+/// Board text copied within the program, for when the system clipboard isn't available.
+///
+/// The system clipboard is opened on first use and kept open: on X11, text
+/// copied to other programs disappears when the clipboard that owns it is
+/// dropped, unless a clipboard manager takes it over.
+#[derive(Resource, Default)]
+struct Clipboard {
+    #[cfg(not(target_family = "wasm"))]
+    system: Option<arboard::Clipboard>,
+    copied: Option<String>,
+}
+
+impl Clipboard {
+    #[cfg(not(target_family = "wasm"))]
+    fn system(&mut self) -> Result<&mut arboard::Clipboard, arboard::Error> {
+        match &mut self.system {
+            Some(system) => Ok(system),
+            system => Ok(system.insert(arboard::Clipboard::new()?)),
+        }
+    }
+
+    fn write(&mut self, text: String) {
+        #[cfg(not(target_family = "wasm"))]
+        if let Err(e) = self.system().and_then(|c| c.set_text(text.clone())) {
+            warn!("failed to copy to the system clipboard: {e}");
+        }
+        self.copied = Some(text);
+    }
+
+    fn read(&mut self) -> Option<String> {
+        #[cfg(not(target_family = "wasm"))]
+        match self.system().and_then(|c| c.get_text()) {
+            Ok(text) => return Some(text),
+            Err(e) => warn!("failed to paste from the system clipboard: {e}"),
+        }
+        self.copied.clone()
+    }
+}
+
+/// The tiles and marbles on the board, for building selection edits.
+pub type BoardTiles<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Tile,
+        &'static TileState,
+        &'static GridExtent,
+        &'static Sprite,
+        Has<Locked>,
+    ),
+>;
+
+/// Read the whole board, noting which tiles are locked.
+pub fn read_tiles(
+    tiles: &BoardTiles,
+    marbles: &Query<(&GridPosition, &Marble)>,
+) -> (Board, Vec<bool>) {
+    let (tiles, locked) = tiles
+        .iter()
+        .map(|(&tile, &state, &extent, sprite, locked)| {
+            let sim_tile = SimTile {
+                tile,
+                extent,
+                flip_x: sprite.flip_x,
+                flip_y: sprite.flip_y,
+                state,
+            };
+            (sim_tile, locked)
+        })
+        .unzip();
+    let marbles = marbles
+        .iter()
+        .map(|(&position, &marble)| PlacedMarble { position, marble })
+        .collect();
+    (Board { tiles, marbles }, locked)
+}
+
+/// The selected tiles, and the marbles sitting on them.
+pub fn selected_group(board: &Board, selection: &Selection) -> Board {
+    let tiles: Vec<SimTile> = board
+        .tiles
+        .iter()
+        .copied()
+        .filter(|sim_tile| selection.0.contains(&sim_tile.extent.origin()))
+        .collect();
+    let marbles = tiles
+        .iter()
+        .flat_map(|sim_tile| board.marbles_on(sim_tile))
+        .collect();
+    Board { tiles, marbles }
+}
+
+/// Check whether a group of tiles would overlap any tile outside the selection.
+pub fn collides(board: &Board, selection: &Selection, group: &Board) -> bool {
+    board
+        .tiles
+        .iter()
+        .filter(|sim_tile| !selection.0.contains(&sim_tile.extent.origin()))
+        .any(|sim_tile| {
+            group
+                .tiles
+                .iter()
+                .any(|new| new.extent.intersects(&sim_tile.extent))
+        })
+}
+
+/// Edits that place every tile in a group, along with its marbles.
+pub fn place_group(group: &Board) -> Vec<Edit> {
+    group
+        .tiles
+        .iter()
+        .map(|&tile| Edit::PlaceTile {
+            tile,
+            marbles: group.marbles_on(&tile),
+        })
+        .collect()
+}
+
+/// Edits that delete every tile in a group, along with its marbles.
+fn delete_group(group: &Board) -> Vec<Edit> {
+    group
+        .tiles
+        .iter()
+        .map(|&tile| Edit::DeleteTile {
+            tile,
+            marbles: group.marbles_on(&tile),
+        })
+        .collect()
+}
+
+/// Give a group's marbles new ids, following on from the marbles already on the board.
+pub fn renumber_marbles(board: &Board, group: &mut Board) {
+    let first_id = Marble::next_id(board.marbles.iter().map(|placed| placed.marble));
+    for (id, placed) in (first_id..).zip(&mut group.marbles) {
+        placed.marble.id = id;
+    }
+}
+
+/// Check that a puzzle's inventory has room for the tiles in a group.
+pub fn inventory_allows(
+    puzzle: Option<&ActivePuzzle>,
+    board: &Board,
+    locked: &[bool],
+    group: &Board,
+) -> bool {
+    let mut placed: Vec<Tile> = board
+        .tiles
+        .iter()
+        .zip(locked)
+        .filter(|&(_, &locked)| !locked)
+        .map(|(sim_tile, _)| sim_tile.tile)
+        .collect();
+    for sim_tile in &group.tiles {
+        if inventory_exhausted(puzzle, sim_tile.tile, placed.iter().copied()) {
+            info!(
+                "no {} tiles left in the puzzle inventory",
+                sim_tile.tile.name()
+            );
+            return false;
+        }
+        placed.push(sim_tile.tile);
+    }
+    true
+}
+
+/// The cursor position in world coordinates, if it is over the board view.
+pub fn cursor_world_pos(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Vec2> {
+    let cursor = window.cursor_position()?;
+    if !camera.logical_viewport_rect()?.contains(cursor) {
+        return None;
+    }
+    camera.viewport_to_world_2d(camera_transform, cursor).ok()
+}
+
+/// The grid offset that moves `anchor` to follow the cursor, snapped to a legal position.
+fn drag_offset(anchor: &SimTile, grab: Vec2, cursor: Vec2) -> IVec2 {
+    let GridPosition(origin) = anchor.extent.origin();
+    let new_origin = anchor.extent.origin().to_world() + (cursor - grab);
+    // Snap from the middle of the grid unit, so the tile doesn't jump on a short drag.
+    let snap_point = new_origin + Vec2::splat(0.5 * PIXELS_PER_GRID_UNIT as f32);
+    let GridPosition(snapped) =
+        GridPosition::from_world_with_offset(snap_point, anchor.tile.offset());
+    snapped - origin
+}
+
+/// Select with a box, or move the selection by dragging one of its tiles.
+#[expect(clippy::too_many_arguments)]
+fn select_mouse(
+    buttons: Res<ButtonInput<MouseButton>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform), With<MainCamera>>,
+    tiles: BoardTiles,
+    marbles: Query<(&GridPosition, &Marble)>,
+    mut selection: ResMut<Selection>,
+    mut drag: ResMut<SelectDrag>,
+    mut commands: Commands,
+) {
+    let (camera, camera_transform) = *camera;
+    let cursor = cursor_world_pos(&window, camera, camera_transform);
+
+    if buttons.just_pressed(MouseButton::Left)
+        && let Some(cursor) = cursor
+    {
+        let (board, _) = read_tiles(&tiles, &marbles);
+        let grabbed = board.tiles.iter().find(|sim_tile| {
+            selection.0.contains(&sim_tile.extent.origin()) && sim_tile.extent.contains(cursor)
+        });
+        *drag = match grabbed {
+            Some(&anchor) => SelectDrag::Move {
+                grab: cursor,
+                anchor,
+            },
+            None => SelectDrag::Box(cursor),
+        };
+    }
+
+    if !buttons.just_released(MouseButton::Left) {
+        return;
+    }
+    match std::mem::take(&mut *drag) {
+        SelectDrag::None => {}
+        SelectDrag::Box(start) => {
+            let end = cursor.unwrap_or(start);
+            let min = start.min(end);
+            let max = start.max(end);
+            selection.0 = tiles
+                .iter()
+                .filter(|&(_, _, _, _, locked)| !locked)
+                .map(|(_, _, extent, _, _)| *extent)
+                .filter(|extent| {
+                    let rect = extent.world_rect();
+                    min.x <= rect.max.x
+                        && max.x >= rect.min.x
+                        && min.y <= rect.max.y
+                        && max.y >= rect.min.y
+                })
+                .map(|extent| extent.origin())
+                .collect();
+            debug!("selected {} tiles", selection.0.len());
+        }
+        SelectDrag::Move { grab, anchor } => {
+            let Some(cursor) = cursor else {
+                return;
+            };
+            let offset = drag_offset(&anchor, grab, cursor);
+            move_selection(&mut commands, &tiles, &marbles, &mut selection, offset);
+        }
+    }
+}
+
+/// Move the selected tiles and their marbles, if there is room.
+fn move_selection(
+    commands: &mut Commands,
+    tiles: &BoardTiles,
+    marbles: &Query<(&GridPosition, &Marble)>,
+    selection: &mut Selection,
+    offset: IVec2,
+) {
+    if offset == IVec2::ZERO || selection.0.is_empty() {
+        return;
+    }
+    debug_assert!(is_legal_shift(offset));
+    let (board, _) = read_tiles(tiles, marbles);
+    let group = selected_group(&board, selection);
+    let moved = group.translated(offset);
+    if collides(&board, selection, &moved) {
+        info!("can't move the selection there: it would overlap other tiles");
+        return;
+    }
+    let mut edits = delete_group(&group);
+    edits.extend(place_group(&moved));
+    commands.trigger(EditBoard(Edit::Batch(edits)));
+    selection.0 = moved
+        .tiles
+        .iter()
+        .map(|sim_tile| sim_tile.extent.origin())
+        .collect();
+}
+
+/// Keyboard shortcuts for the select tool.
+#[expect(clippy::too_many_arguments)]
+fn select_keyboard(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform), With<MainCamera>>,
+    tiles: BoardTiles,
+    marbles: Query<(&GridPosition, &Marble)>,
+    puzzle: Option<Res<ActivePuzzle>>,
+    mut selection: ResMut<Selection>,
+    mut clipboard: ResMut<Clipboard>,
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<SimState>>,
+) {
+    if keyboard.just_pressed(KeyCode::Escape) {
+        next_state.set(SimState::Idle);
+        return;
+    }
+
+    let mut offset = IVec2::ZERO;
+    if keyboard.just_pressed(KeyCode::ArrowLeft) {
+        offset.x -= ARROW_STEP.x;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowRight) {
+        offset.x += ARROW_STEP.x;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowUp) {
+        offset.y += ARROW_STEP.y;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowDown) {
+        offset.y -= ARROW_STEP.y;
+    }
+    if offset != IVec2::ZERO {
+        // The move is queued, so the board read below would be out of date.
+        move_selection(&mut commands, &tiles, &marbles, &mut selection, offset);
+        return;
+    }
+
+    let (board, locked) = read_tiles(&tiles, &marbles);
+    let group = selected_group(&board, &selection);
+
+    if keyboard.any_just_pressed([KeyCode::Delete, KeyCode::Backspace]) && !group.tiles.is_empty() {
+        commands.trigger(EditBoard(Edit::Batch(delete_group(&group))));
+        selection.0.clear();
+        return;
+    }
+
+    if !keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    if keyboard.any_just_pressed([KeyCode::KeyC, KeyCode::KeyX]) && !group.tiles.is_empty() {
+        clipboard.write(group.shifted_to_origin().to_ron());
+        info!("copied {} tiles", group.tiles.len());
+        if keyboard.just_pressed(KeyCode::KeyX) {
+            commands.trigger(EditBoard(Edit::Batch(delete_group(&group))));
+            selection.0.clear();
+        }
+    }
+    if keyboard.just_pressed(KeyCode::KeyD) && !group.tiles.is_empty() {
+        let mut copy = group.clone();
+        renumber_marbles(&board, &mut copy);
+        let min_x = group
+            .tiles
+            .iter()
+            .map(|t| t.extent.origin().0.x)
+            .min()
+            .unwrap_or(0);
+        let max_x = group
+            .tiles
+            .iter()
+            .map(|t| t.extent.origin().0.x + t.extent.width())
+            .max()
+            .unwrap_or(0);
+        // Shift right by the group's width, rounded up to keep offsets, until there is room.
+        let width = max_x - min_x;
+        let step = width + width.rem_euclid(2);
+        let nowhere = Selection(Vec::new());
+        let placed = (1..=MAX_DUPLICATE_TRIES)
+            .map(|n| copy.translated(IVec2::new(step * n, 0)))
+            .find(|moved| !collides(&board, &nowhere, moved));
+        let Some(copy) = placed else {
+            info!("no room to duplicate the selection");
+            return;
+        };
+        if !inventory_allows(puzzle.as_deref(), &board, &locked, &copy) {
+            return;
+        }
+        commands.trigger(EditBoard(Edit::Batch(place_group(&copy))));
+        selection.0 = copy
+            .tiles
+            .iter()
+            .map(|sim_tile| sim_tile.extent.origin())
+            .collect();
+    }
+    if keyboard.just_pressed(KeyCode::KeyV) {
+        let (camera, camera_transform) = *camera;
+        let Some(cursor) = cursor_world_pos(&window, camera, camera_transform) else {
+            return;
+        };
+        let Some(text) = clipboard.read() else {
+            return;
+        };
+        let mut pasted = match Board::from_ron(&text) {
+            Ok(pasted) => pasted,
+            Err(e) => {
+                info!("the clipboard doesn't hold a board: {e}");
+                return;
+            }
+        };
+        // Put the leftmost tile under the cursor.
+        let Some(anchor) = pasted
+            .tiles
+            .iter()
+            .min_by_key(|sim_tile| sim_tile.extent.origin().0.x)
+        else {
+            return;
+        };
+        let GridPosition(origin) = anchor.extent.origin();
+        let GridPosition(target) =
+            GridPosition::from_world_with_offset(cursor, anchor.tile.offset());
+        pasted = pasted.translated(target - origin);
+        renumber_marbles(&board, &mut pasted);
+        if collides(&board, &Selection(Vec::new()), &pasted) {
+            info!("can't paste there: it would overlap other tiles");
+            return;
+        }
+        if !inventory_allows(puzzle.as_deref(), &board, &locked, &pasted) {
+            return;
+        }
+        info!("pasted {} tiles", pasted.tiles.len());
+        commands.trigger(EditBoard(Edit::Batch(place_group(&pasted))));
+        selection.0 = pasted
+            .tiles
+            .iter()
+            .map(|sim_tile| sim_tile.extent.origin())
+            .collect();
+    }
+}
+
+/// Draw the selection box, or outlines where a dragged group would land.
+fn draw_drag(
+    drag: Res<SelectDrag>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform), With<MainCamera>>,
+    selection: Res<Selection>,
+    tiles: Query<&GridExtent>,
+    mut gizmos: Gizmos,
+) {
+    let (camera, camera_transform) = *camera;
+    let Some(cursor) = cursor_world_pos(&window, camera, camera_transform) else {
+        return;
+    };
+    match *drag {
+        SelectDrag::None => {}
+        SelectDrag::Box(start) => {
+            let rect = Rect::from_corners(start, cursor);
+            gizmos.rect_2d(rect.center(), rect.size(), SELECT_BOX_COLOR);
+        }
+        SelectDrag::Move { grab, anchor } => {
+            let offset = drag_offset(&anchor, grab, cursor).as_vec2() * PIXELS_PER_GRID_UNIT as f32;
+            for extent in tiles
+                .iter()
+                .filter(|extent| selection.0.contains(&extent.origin()))
+            {
+                let rect = extent.world_rect();
+                gizmos.rect_2d(rect.center() + offset, rect.size(), SELECT_BOX_COLOR);
+            }
+        }
+    }
+}
+
+/// Tint the selected tiles, including ones respawned by a move.
+fn tint_selection(
+    selection: Res<Selection>,
+    mut tiles: Query<(Ref<GridExtent>, &mut Sprite), Without<Locked>>,
+) {
+    for (extent, mut sprite) in &mut tiles {
+        let selected = selection.0.contains(&extent.origin());
+        // New tiles are only touched if selected, to leave puzzle tints alone.
+        if selection.is_changed() || (extent.is_added() && selected) {
+            let tint = if selected {
+                SELECTED_TINT
+            } else {
+                Color::WHITE
+            };
+            if sprite.color != tint {
+                sprite.color = tint;
+            }
+        }
+    }
+}
+
+fn clear_selection(mut selection: ResMut<Selection>, mut drag: ResMut<SelectDrag>) {
+    selection.0.clear();
+    *drag = SelectDrag::None;
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::ivec2;
+
+    use super::*;
+
+    fn place(tile: Tile, x: i32, y: i32) -> SimTile {
+        SimTile {
+            tile,
+            extent: tile.extent(GridPosition(ivec2(x, y))),
+            flip_x: false,
+            flip_y: false,
+            state: tile.initial_state(),
+        }
+    }
+
+    #[test]
+    fn drags_keep_offsets() {
+        for anchor in [place(Tile::Path, 0, 0), place(Tile::Shimmy, 1, 4)] {
+            let grab = anchor.extent.world_rect().center();
+            for cursor in [vec2(3.0, 1.0), vec2(-13.0, 30.0), vec2(22.5, -17.0)] {
+                let offset = drag_offset(&anchor, grab, grab + cursor);
+                assert!(is_legal_shift(offset), "{offset} from {cursor}");
+            }
+            // A small wobble doesn't move anything.
+            assert_eq!(
+                drag_offset(&anchor, grab, grab + vec2(1.0, -1.0)),
+                IVec2::ZERO
+            );
+        }
+    }
+
+    #[test]
+    fn duplicates_get_new_marble_ids() {
+        let tile = place(Tile::Path, 0, 0);
+        let marble = |id| PlacedMarble {
+            position: GridPosition(ivec2(2, 3)),
+            marble: Marble {
+                id,
+                color: Default::default(),
+            },
+        };
+        let board = Board {
+            tiles: vec![tile],
+            marbles: vec![marble(0), marble(5)],
+        };
+        let mut copy = board.clone();
+        renumber_marbles(&board, &mut copy);
+        let ids: Vec<u32> = copy.marbles.iter().map(|placed| placed.marble.id).collect();
+        assert_eq!(ids, [6, 7]);
+        assert_eq!(place_group(&copy).len(), 1);
+    }
+}
@@ -0,0 +1,156 @@
+//! Boards encoded as short URL-safe strings, for links to the web build.
+//!
+//! A board is written in the compact text format, compressed with deflate and
+//! encoded as unpadded URL-safe base64. The web build keeps the encoding in
+//! the page fragment, as `#board=...`, so that a link reproduces the machine.
+
+use std::fmt::Display;
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+
+use crate::board::{Board, BoardError};
+
+/// The name of the page fragment parameter that holds a board.
+const FRAGMENT_KEY: &str = "board";
+
+/// The deflate compression level, from 0 to 10.
+const COMPRESSION_LEVEL: u8 = 9;
+
+/// The largest board text that will be decoded, to stop a small link
+/// expanding into something enormous.
+const MAX_TEXT_LEN: usize = 1 << 20;
+
+/// Encode a board as a URL-safe string.
+pub fn encode(board: &Board) -> String {
+    let compressed = compress_to_vec(board.to_compact_ron().as_bytes(), COMPRESSION_LEVEL);
+    URL_SAFE_NO_PAD.encode(compressed)
+}
+
+/// Decode a board from a string made by `encode`.
+pub fn decode(encoded: &str) -> Result<Board, ShareError> {
+    let compressed = URL_SAFE_NO_PAD
+        .decode(encoded.trim())
+        .map_err(ShareError::Base64)?;
+    let bytes = decompress_to_vec_with_limit(&compressed, MAX_TEXT_LEN)
+        .map_err(|e| ShareError::Inflate(e.status))?;
+    let text = String::from_utf8(bytes).map_err(|_| ShareError::NotText)?;
+    Board::from_ron(&text).map_err(ShareError::Board)
+}
+
+/// The page fragment for a board, including the leading `#`.
+pub fn to_fragment(board: &Board) -> String {
+    format!("#{FRAGMENT_KEY}={}", encode(board))
+}
+
+/// Find and decode the board in a page fragment, if it has one.
+///
+/// The fragment may have other `&`-separated parameters, and the leading `#` is optional.
+pub fn from_fragment(fragment: &str) -> Option<Result<Board, ShareError>> {
+    fragment
+        .trim_start_matches('#')
+        .split('&')
+        .find_map(|param| param.strip_prefix(FRAGMENT_KEY)?.strip_prefix('='))
+        .map(decode)
+}
+
+/// An error decoding a shared board.
+#[derive(Debug)]
+pub enum ShareError {
+    Base64(base64::DecodeError),
+    Inflate(miniz_oxide::inflate::TINFLStatus),
+    NotText,
+    Board(BoardError),
+}
+
+impl Display for ShareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShareError::Base64(e) => write!(f, "not a board link: {e}"),
+            ShareError::Inflate(status) => write!(f, "corrupt board link: {status:?}"),
+            ShareError::NotText => write!(f, "corrupt board link: not text"),
+            ShareError::Board(e) => write!(f, "bad board in link: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ShareError {}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::ivec2;
+
+    use super::*;
+    use crate::{
+        board::PlacedMarble,
+        grid::GridPosition,
+        sim::SimTile,
+        tile::{Marble, MarbleColor, Tile, TileState},
+    };
+
+    fn sample_board() -> Board {
+        let place = |tile: Tile, x, y, flip_x| SimTile {
+            tile,
+            extent: tile.extent(GridPosition(ivec2(x, y))),
+            flip_x,
+            flip_y: false,
+            state: tile.initial_state(),
+        };
+        // A tile that isn't in its initial state, as the inspector can leave it.
+        let diverted = SimTile {
+            state: TileState::Lever { diverted: true },
+            ..place(Tile::Switch, -2, 4, true)
+        };
+        Board {
+            tiles: vec![
+                place(Tile::Path, 0, 0, false),
+                diverted,
+                place(Tile::Shimmy, 9, -4, false),
+            ],
+            marbles: vec![PlacedMarble {
+                position: GridPosition(ivec2(-4, 6)),
+                marble: Marble {
+                    id: 7,
+                    color: MarbleColor::Blue,
+                },
+            }],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let board = sample_board();
+        let encoded = encode(&board);
+        assert!(
+            encoded
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+            "{encoded}"
+        );
+        assert!(encoded.len() < board.to_ron().len());
+        assert_eq!(decode(&encoded).unwrap(), board);
+
+        let empty = Board::default();
+        assert_eq!(decode(&encode(&empty)).unwrap(), empty);
+    }
+
+    #[test]
+    fn fragments() {
+        let board = sample_board();
+        let fragment = to_fragment(&board);
+        assert!(fragment.starts_with("#board="));
+        assert_eq!(from_fragment(&fragment).unwrap().unwrap(), board);
+        let with_others = format!("#zoom=4&{}", &fragment[1..]);
+        assert_eq!(from_fragment(&with_others).unwrap().unwrap(), board);
+        assert!(from_fragment("").is_none());
+        assert!(from_fragment("#boards=x").is_none());
+    }
+
+    #[test]
+    fn bad_links() {
+        assert!(matches!(decode("not base64!"), Err(ShareError::Base64(_))));
+        assert!(matches!(decode("AAAA"), Err(ShareError::Inflate(_))));
+        let not_a_board = URL_SAFE_NO_PAD.encode(compress_to_vec(b"(nope)", 9));
+        assert!(matches!(decode(&not_a_board), Err(ShareError::Board(_))));
+    }
+}
@@ -0,0 +1,85 @@
+//! Keeping the board in the page fragment of the web build.
+//!
+//! On startup, a board in the fragment replaces the empty board. After every
+//! edit, the fragment is rewritten, so the address bar always holds a link to
+//! the current machine. While a simulation is running or paused, the board is
+//! part way through a run, so the fragment is left alone. Elsewhere there is no
+//! fragment, and this does nothing.
+
+use bevy::prelude::*;
+
+use crate::{
+    grid::GridPosition,
+    history::History,
+    save_load::{read_board, spawn_board},
+    share,
+    simulate::ActiveSimulation,
+    tile::{GridExtent, Marble, Tile, TileState},
+};
+
+pub struct ShareLinkPlugin;
+
+impl Plugin for ShareLinkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_board_from_fragment)
+            .add_systems(
+                Update,
+                update_fragment.run_if(
+                    resource_changed::<History>.and(not(resource_exists::<ActiveSimulation>)),
+                ),
+            );
+    }
+}
+
+fn load_board_from_fragment(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let Some(fragment) = read_fragment() else {
+        return;
+    };
+    match share::from_fragment(&fragment) {
+        Some(Ok(board)) => {
+            info!("loaded board from the page address");
+            spawn_board(&mut commands, &asset_server, &board);
+        }
+        Some(Err(e)) => error!("failed to load the board in the page address: {e}"),
+        None => {}
+    }
+}
+
+/// Write the current board into the page fragment.
+fn update_fragment(
+    tiles: Query<(&Tile, &TileState, &GridExtent, &Sprite)>,
+    marbles: Query<(&GridPosition, &Marble)>,
+    mut written: Local<String>,
+) {
+    let fragment = share::to_fragment(&read_board(&tiles, &marbles));
+    if *written != fragment {
+        write_fragment(&fragment);
+        *written = fragment;
+    }
+}
+
+#[cfg(target_family = "wasm")]
+fn read_fragment() -> Option<String> {
+    web_sys::window()?.location().hash().ok()
+}
+
+#[cfg(target_family = "wasm")]
+fn write_fragment(fragment: &str) {
+    use web_sys::wasm_bindgen::JsValue;
+
+    let Some(history) = web_sys::window().and_then(|window| window.history().ok()) else {
+        return;
+    };
+    // Replace the page's history entry, rather than adding one for every edit.
+    if let Err(e) = history.replace_state_with_url(&JsValue::NULL, "", Some(fragment)) {
+        warn!("failed to update the page address: {e:?}");
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn read_fragment() -> Option<String> {
+    None
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn write_fragment(_fragment: &str) {}
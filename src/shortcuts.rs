@@ -0,0 +1,720 @@
+//! Keyboard shortcuts that work in any mode, a keyboard cursor, and a help overlay.
+//!
+//! The number keys pick a tile, and single keys switch modes and drive the
+//! simulation. The grid cursor moves one tile position at a time and clicks
+//! where it is, so tiles and marbles can be placed without a mouse. It is
+//! also driven by gamepads.
+//!
+//! Bindings can be changed in `shortcuts.ron`, which maps shortcuts to keys,
+//! for example `{ Delete: "X", PanRight: "Right" }`. Shortcuts that aren't
+//! listed keep their default keys. Shortcuts that belong to one mode, such as
+//! flipping the ghost tile, are fixed, but are listed in the help overlay too.
+
+use std::{fmt, path::Path};
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    MainCamera, MouseClick, SimState,
+    blueprint::BlueprintNamePrompt,
+    grid::{GRID_UNITS_PER_TILE, GridPosition, PIXELS_PER_GRID_UNIT},
+    grid_overlay::ToggleGrid,
+    save_load::read_file,
+    tile::{ALL_TILES, Offset},
+    ui::{Action, RunAction, UiTileSelected},
+};
+
+/// The file that shortcut bindings are read from.
+const SHORTCUTS_FILE: &str = "shortcuts.ron";
+
+/// How far the grid cursor moves horizontally, in grid units.
+const CURSOR_STEP: i32 = 2;
+
+/// The colour of the grid cursor.
+const CURSOR_COLOR: Color = Color::srgb(1.0, 0.9, 0.3);
+
+/// Shortcuts that belong to one mode, and can't be rebound.
+const FIXED_SHORTCUTS: &[(&str, &str)] = &[
+    ("Ctrl+Z", "undo"),
+    ("Ctrl+Y, Ctrl+Shift+Z", "redo"),
+    ("Ctrl+S", "save the board"),
+    ("Ctrl+O", "load the board"),
+    ("Escape", "leave the current mode"),
+    ("Space", "next tile, while placing"),
+    ("Left, Up", "flip the ghost or hovered tile"),
+    ("Arrows", "move the selection"),
+    ("Delete", "delete the selection"),
+    (
+        "Ctrl+C, Ctrl+X, Ctrl+V",
+        "copy, cut and paste the selection",
+    ),
+    ("Ctrl+D", "duplicate the selection"),
+    ("Ctrl+B", "save the selection as a blueprint"),
+];
+
+pub struct ShortcutsPlugin;
+
+impl Plugin for ShortcutsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_keymap(Path::new(SHORTCUTS_FILE)))
+            .init_resource::<GridCursor>()
+            .add_event::<CursorInput>()
+            .add_observer(toggle_help)
+            .add_systems(
+                Update,
+                (
+                    (global_shortcuts, cursor_keyboard)
+                        .run_if(not(resource_exists::<BlueprintNamePrompt>)),
+                    move_cursor,
+                    forget_cursor,
+                    draw_cursor,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Something that can be done with a rebindable key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Shortcut {
+    /// Pick the tile with this index in the tile row, counting from 0.
+    PickTile(usize),
+    PlaceMarbles,
+    Delete,
+    Select,
+    PlayPause,
+    Rewind,
+    Step,
+    Slower,
+    Faster,
+    FitToView,
+    ToggleTrails,
+    ToggleGrid,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    CursorUp,
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    /// Click at the keyboard cursor.
+    Click,
+    Help,
+}
+
+impl Shortcut {
+    /// A description for the help overlay.
+    fn describe(self) -> String {
+        let text = match self {
+            Shortcut::PickTile(index) => {
+                return match ALL_TILES.get(index) {
+                    Some(tile) => format!("place {} tiles", tile.name()),
+                    None => "place nothing".into(),
+                };
+            }
+            Shortcut::PlaceMarbles => "place marbles",
+            Shortcut::Delete => "delete tiles",
+            Shortcut::Select => "select tiles",
+            Shortcut::PlayPause => "play or pause",
+            Shortcut::Rewind => "rewind",
+            Shortcut::Step => "step one tick",
+            Shortcut::Slower => "run slower",
+            Shortcut::Faster => "run faster",
+            Shortcut::FitToView => "fit the board to the view",
+            Shortcut::ToggleTrails => "show or hide marble trails",
+            Shortcut::ToggleGrid => "show or hide the grid",
+            Shortcut::PanUp => "pan up",
+            Shortcut::PanDown => "pan down",
+            Shortcut::PanLeft => "pan left",
+            Shortcut::PanRight => "pan right",
+            Shortcut::CursorUp => "move the keyboard cursor up",
+            Shortcut::CursorDown => "move the keyboard cursor down",
+            Shortcut::CursorLeft => "move the keyboard cursor left",
+            Shortcut::CursorRight => "move the keyboard cursor right",
+            Shortcut::Click => "click at the keyboard cursor",
+            Shortcut::Help => "show or hide this help",
+        };
+        text.into()
+    }
+}
+
+/// A key, along with the modifiers that must be held with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyCombo {
+    pub key: KeyCode,
+    pub ctrl: bool,
+    pub shift: bool,
+}
+
+impl KeyCombo {
+    const fn new(key: KeyCode) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            shift: false,
+        }
+    }
+
+    const fn shift(key: KeyCode) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            shift: true,
+        }
+    }
+
+    /// Check that exactly the right modifiers are held.
+    fn modifiers_match(&self, keyboard: &ButtonInput<KeyCode>) -> bool {
+        let ctrl = keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+        let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        ctrl == self.ctrl && shift == self.shift
+    }
+
+    pub fn just_pressed(&self, keyboard: &ButtonInput<KeyCode>) -> bool {
+        keyboard.just_pressed(self.key) && self.modifiers_match(keyboard)
+    }
+
+    pub fn pressed(&self, keyboard: &ButtonInput<KeyCode>) -> bool {
+        keyboard.pressed(self.key) && self.modifiers_match(keyboard)
+    }
+}
+
+impl TryFrom<String> for KeyCombo {
+    type Error = String;
+
+    /// Parse a key name with optional modifiers, such as `Ctrl+Shift+Z`.
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let name = parts.pop().unwrap_or_default();
+        let key = KEY_NAMES
+            .iter()
+            .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+            .map(|&(_, key)| key)
+            .ok_or_else(|| format!("unknown key {name:?}"))?;
+        let mut combo = KeyCombo::new(key);
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" => combo.ctrl = true,
+                "shift" => combo.shift = true,
+                _ => return Err(format!("unknown modifier {modifier:?}")),
+            }
+        }
+        Ok(combo)
+    }
+}
+
+impl From<KeyCombo> for String {
+    fn from(combo: KeyCombo) -> Self {
+        combo.to_string()
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        let name = KEY_NAMES
+            .iter()
+            .find(|&&(_, key)| key == self.key)
+            .map_or("?", |&(name, _)| name);
+        write!(f, "{name}")
+    }
+}
+
+/// The keys that can be named in the shortcuts file.
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("A", KeyCode::KeyA),
+    ("B", KeyCode::KeyB),
+    ("C", KeyCode::KeyC),
+    ("D", KeyCode::KeyD),
+    ("E", KeyCode::KeyE),
+    ("F", KeyCode::KeyF),
+    ("G", KeyCode::KeyG),
+    ("H", KeyCode::KeyH),
+    ("I", KeyCode::KeyI),
+    ("J", KeyCode::KeyJ),
+    ("K", KeyCode::KeyK),
+    ("L", KeyCode::KeyL),
+    ("M", KeyCode::KeyM),
+    ("N", KeyCode::KeyN),
+    ("O", KeyCode::KeyO),
+    ("P", KeyCode::KeyP),
+    ("Q", KeyCode::KeyQ),
+    ("R", KeyCode::KeyR),
+    ("S", KeyCode::KeyS),
+    ("T", KeyCode::KeyT),
+    ("U", KeyCode::KeyU),
+    ("V", KeyCode::KeyV),
+    ("W", KeyCode::KeyW),
+    ("X", KeyCode::KeyX),
+    ("Y", KeyCode::KeyY),
+    ("Z", KeyCode::KeyZ),
+    ("0", KeyCode::Digit0),
+    ("1", KeyCode::Digit1),
+    ("2", KeyCode::Digit2),
+    ("3", KeyCode::Digit3),
+    ("4", KeyCode::Digit4),
+    ("5", KeyCode::Digit5),
+    ("6", KeyCode::Digit6),
+    ("7", KeyCode::Digit7),
+    ("8", KeyCode::Digit8),
+    ("9", KeyCode::Digit9),
+    ("F1", KeyCode::F1),
+    ("F2", KeyCode::F2),
+    ("F3", KeyCode::F3),
+    ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5),
+    ("F6", KeyCode::F6),
+    ("F7", KeyCode::F7),
+    ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9),
+    ("F10", KeyCode::F10),
+    ("F11", KeyCode::F11),
+    ("F12", KeyCode::F12),
+    ("Up", KeyCode::ArrowUp),
+    ("Down", KeyCode::ArrowDown),
+    ("Left", KeyCode::ArrowLeft),
+    ("Right", KeyCode::ArrowRight),
+    ("Space", KeyCode::Space),
+    ("Enter", KeyCode::Enter),
+    ("Escape", KeyCode::Escape),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("Delete", KeyCode::Delete),
+    ("Insert", KeyCode::Insert),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Minus", KeyCode::Minus),
+    ("Equal", KeyCode::Equal),
+    ("Comma", KeyCode::Comma),
+    ("Period", KeyCode::Period),
+    ("Slash", KeyCode::Slash),
+    ("Semicolon", KeyCode::Semicolon),
+    ("Quote", KeyCode::Quote),
+    ("Backquote", KeyCode::Backquote),
+    ("Backslash", KeyCode::Backslash),
+    ("BracketLeft", KeyCode::BracketLeft),
+    ("BracketRight", KeyCode::BracketRight),
+];
+
+/// The key bound to each shortcut, in the order they are listed in the help overlay.
+#[derive(Resource, Debug)]
+pub struct Keymap(Vec<(Shortcut, KeyCombo)>);
+
+impl Default for Keymap {
+    fn default() -> Self {
+        const DIGITS: [KeyCode; 10] = [
+            KeyCode::Digit1,
+            KeyCode::Digit2,
+            KeyCode::Digit3,
+            KeyCode::Digit4,
+            KeyCode::Digit5,
+            KeyCode::Digit6,
+            KeyCode::Digit7,
+            KeyCode::Digit8,
+            KeyCode::Digit9,
+            KeyCode::Digit0,
+        ];
+        let mut bindings: Vec<(Shortcut, KeyCombo)> = (0..ALL_TILES.len())
+            .zip(DIGITS)
+            .map(|(index, key)| (Shortcut::PickTile(index), KeyCombo::new(key)))
+            .collect();
+        bindings.extend([
+            (Shortcut::PlaceMarbles, KeyCombo::new(KeyCode::KeyM)),
+            (Shortcut::Delete, KeyCombo::new(KeyCode::KeyD)),
+            (Shortcut::Select, KeyCombo::new(KeyCode::KeyE)),
+            (Shortcut::PlayPause, KeyCombo::new(KeyCode::KeyP)),
+            (Shortcut::Rewind, KeyCombo::new(KeyCode::KeyR)),
+            (Shortcut::Step, KeyCombo::new(KeyCode::Period)),
+            (Shortcut::Slower, KeyCombo::new(KeyCode::Minus)),
+            (Shortcut::Faster, KeyCombo::new(KeyCode::Equal)),
+            (Shortcut::FitToView, KeyCombo::new(KeyCode::KeyF)),
+            (Shortcut::ToggleTrails, KeyCombo::new(KeyCode::KeyT)),
+            (Shortcut::ToggleGrid, KeyCombo::new(KeyCode::KeyG)),
+            // D deletes, so panning needs Shift.
+            (Shortcut::PanUp, KeyCombo::shift(KeyCode::KeyW)),
+            (Shortcut::PanDown, KeyCombo::shift(KeyCode::KeyS)),
+            (Shortcut::PanLeft, KeyCombo::shift(KeyCode::KeyA)),
+            (Shortcut::PanRight, KeyCombo::shift(KeyCode::KeyD)),
+            (Shortcut::CursorUp, KeyCombo::new(KeyCode::KeyI)),
+            (Shortcut::CursorDown, KeyCombo::new(KeyCode::KeyK)),
+            (Shortcut::CursorLeft, KeyCombo::new(KeyCode::KeyJ)),
+            (Shortcut::CursorRight, KeyCombo::new(KeyCode::KeyL)),
+            (Shortcut::Click, KeyCombo::new(KeyCode::Enter)),
+            (Shortcut::Help, KeyCombo::new(KeyCode::KeyH)),
+        ]);
+        Self(bindings)
+    }
+}
+
+impl Keymap {
+    /// Read bindings from the text of a shortcuts file, on top of the defaults.
+    pub fn from_ron(text: &str) -> Result<Self, ron::error::SpannedError> {
+        let overrides: Vec<(Shortcut, KeyCombo)> =
+            ron::from_str::<std::collections::HashMap<Shortcut, KeyCombo>>(text)?
+                .into_iter()
+                .collect();
+        let mut keymap = Self::default();
+        for (shortcut, combo) in overrides {
+            match keymap.0.iter_mut().find(|(bound, _)| *bound == shortcut) {
+                Some(binding) => binding.1 = combo,
+                None => keymap.0.push((shortcut, combo)),
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// The key bound to a shortcut.
+    pub fn combo(&self, shortcut: Shortcut) -> Option<KeyCombo> {
+        self.0
+            .iter()
+            .find(|(bound, _)| *bound == shortcut)
+            .map(|&(_, combo)| combo)
+    }
+
+    /// Check whether a shortcut's key was pressed this frame.
+    pub fn just_pressed(&self, shortcut: Shortcut, keyboard: &ButtonInput<KeyCode>) -> bool {
+        self.combo(shortcut)
+            .is_some_and(|combo| combo.just_pressed(keyboard))
+    }
+
+    /// Check whether a shortcut's key is held down.
+    pub fn pressed(&self, shortcut: Shortcut, keyboard: &ButtonInput<KeyCode>) -> bool {
+        self.combo(shortcut)
+            .is_some_and(|combo| combo.pressed(keyboard))
+    }
+
+    /// Pairs of shortcuts that are bound to the same key.
+    fn clashes(&self) -> Vec<(Shortcut, Shortcut)> {
+        let mut clashes = Vec::new();
+        for (i, (first, combo)) in self.0.iter().enumerate() {
+            for (second, other) in &self.0[i + 1..] {
+                if combo == other {
+                    clashes.push((*first, *second));
+                }
+            }
+        }
+        clashes
+    }
+}
+
+/// Read the shortcuts file, falling back to the default bindings.
+fn load_keymap(path: &Path) -> Keymap {
+    let keymap = match read_file(path) {
+        Ok(text) => match Keymap::from_ron(&text) {
+            Ok(keymap) => {
+                info!("loaded shortcuts from {}", path.display());
+                keymap
+            }
+            Err(e) => {
+                error!("failed to load {}: {e}", path.display());
+                Keymap::default()
+            }
+        },
+        // Most people won't have a shortcuts file.
+        Err(_) => Keymap::default(),
+    };
+    for (first, second) in keymap.clashes() {
+        warn!("{first:?} and {second:?} are bound to the same key");
+    }
+    keymap
+}
+
+fn global_shortcuts(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    keymap: Res<Keymap>,
+    state: Res<State<SimState>>,
+    mut next_state: ResMut<NextState<SimState>>,
+    mut commands: Commands,
+) {
+    for &(shortcut, combo) in &keymap.0 {
+        if !combo.just_pressed(&keyboard) {
+            continue;
+        }
+        let action = match shortcut {
+            Shortcut::PickTile(index) => {
+                if let Some(&tile) = ALL_TILES.get(index) {
+                    commands.trigger(UiTileSelected(tile));
+                }
+                continue;
+            }
+            Shortcut::PlaceMarbles => {
+                next_state.set(SimState::PlacingMarbles);
+                continue;
+            }
+            Shortcut::ToggleGrid => {
+                commands.trigger(ToggleGrid);
+                continue;
+            }
+            Shortcut::Delete => Action::Delete,
+            Shortcut::Select => Action::Select,
+            Shortcut::PlayPause if *state.get() == SimState::Running => Action::Pause,
+            Shortcut::PlayPause => Action::Play,
+            Shortcut::Rewind => Action::Rewind,
+            Shortcut::Step => Action::Step,
+            Shortcut::Slower => Action::Slower,
+            Shortcut::Faster => Action::Faster,
+            Shortcut::FitToView => Action::FitToView,
+            Shortcut::ToggleTrails => Action::ToggleTrails,
+            Shortcut::Help => Action::Help,
+            // Held keys, handled by the camera and the keyboard cursor.
+            Shortcut::PanUp
+            | Shortcut::PanDown
+            | Shortcut::PanLeft
+            | Shortcut::PanRight
+            | Shortcut::CursorUp
+            | Shortcut::CursorDown
+            | Shortcut::CursorLeft
+            | Shortcut::CursorRight
+            | Shortcut::Click => continue,
+        };
+        commands.trigger(RunAction(action));
+    }
+}
+
+/// The grid cell the keyboard or gamepad cursor is in, if it has been used
+/// since the mouse last moved.
+#[derive(Resource, Default)]
+pub struct GridCursor(pub Option<GridPosition>);
+
+impl GridCursor {
+    /// The point the cursor clicks at: the middle of the bottom left grid unit of its cell.
+    pub fn point(&self) -> Option<Vec2> {
+        let position = self.0?;
+        Some(position.to_world() + Vec2::splat(0.5 * PIXELS_PER_GRID_UNIT as f32))
+    }
+}
+
+/// Move the grid cursor, or click with it.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorInput {
+    /// Move by this many cells: tile rows vertically, and half tiles horizontally.
+    Move(IVec2),
+    Click,
+}
+
+fn cursor_keyboard(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut input: EventWriter<CursorInput>,
+) {
+    let mut step = IVec2::ZERO;
+    if keymap.just_pressed(Shortcut::CursorLeft, &keyboard) {
+        step.x -= 1;
+    }
+    if keymap.just_pressed(Shortcut::CursorRight, &keyboard) {
+        step.x += 1;
+    }
+    if keymap.just_pressed(Shortcut::CursorUp, &keyboard) {
+        step.y += 1;
+    }
+    if keymap.just_pressed(Shortcut::CursorDown, &keyboard) {
+        step.y -= 1;
+    }
+    if step != IVec2::ZERO {
+        input.write(CursorInput::Move(step));
+    }
+    if keymap.just_pressed(Shortcut::Click, &keyboard) {
+        input.write(CursorInput::Click);
+    }
+}
+
+/// Move the grid cursor, and click with it.
+///
+/// The cursor moves the ghost tile or marble as if the mouse had moved there,
+/// and clicks as if the mouse had clicked there.
+pub fn move_cursor(
+    mut input: EventReader<CursorInput>,
+    mut cursor: ResMut<GridCursor>,
+    window: Single<(Entity, &Window), With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut cursor_moved: EventWriter<CursorMoved>,
+    mut clicks: EventWriter<MouseClick>,
+) {
+    let (window_entity, window) = *window;
+    let (camera, camera_transform) = *camera;
+    for &input in input.read() {
+        // Start from the mouse, or from the middle of the view.
+        let start = || {
+            let pos = window
+                .cursor_position()
+                .and_then(|pos| camera.viewport_to_world_2d(camera_transform, pos).ok())
+                .unwrap_or(camera_transform.translation().truncate());
+            GridPosition::from_world_with_offset(pos, Offset::Even)
+        };
+        let GridPosition(position) = cursor.0.unwrap_or_else(start);
+        let step = match input {
+            CursorInput::Move(cells) => cells * IVec2::new(CURSOR_STEP, GRID_UNITS_PER_TILE),
+            CursorInput::Click => IVec2::ZERO,
+        };
+        cursor.0 = Some(GridPosition(position + step));
+        let Some(point) = cursor.point() else {
+            continue;
+        };
+        match input {
+            CursorInput::Move(_) => {
+                if let Ok(window_pos) =
+                    camera.world_to_viewport(camera_transform, point.extend(0.0))
+                {
+                    cursor_moved.write(CursorMoved {
+                        window: window_entity,
+                        position: window_pos,
+                        delta: None,
+                    });
+                }
+            }
+            CursorInput::Click => {
+                clicks.write(MouseClick { world_pos: point });
+            }
+        }
+    }
+}
+
+/// Hide the grid cursor when the mouse moves.
+fn forget_cursor(mut cursor_moved: EventReader<CursorMoved>, mut cursor: ResMut<GridCursor>) {
+    // Only real mouse movements have a delta.
+    if cursor_moved.read().any(|event| event.delta.is_some()) {
+        cursor.0 = None;
+    }
+}
+
+fn draw_cursor(cursor: Res<GridCursor>, mut gizmos: Gizmos) {
+    let Some(GridPosition(position)) = cursor.0 else {
+        return;
+    };
+    let min = GridPosition(position).to_world();
+    let max = GridPosition(position + IVec2::new(CURSOR_STEP, GRID_UNITS_PER_TILE)).to_world();
+    let rect = Rect::from_corners(min, max);
+    gizmos.rect_2d(rect.center(), rect.size(), CURSOR_COLOR);
+}
+
+/// Show or hide the help overlay.
+#[derive(Event)]
+pub struct ToggleHelp;
+
+/// The help overlay listing the keyboard shortcuts.
+#[derive(Component)]
+struct HelpOverlay;
+
+/// The lines of the help overlay.
+fn help_lines(keymap: &Keymap) -> Vec<(String, String)> {
+    let bound = keymap
+        .0
+        .iter()
+        .map(|&(shortcut, combo)| (combo.to_string(), shortcut.describe()));
+    let fixed = FIXED_SHORTCUTS
+        .iter()
+        .map(|&(keys, description)| (keys.to_owned(), description.to_owned()));
+    bound.chain(fixed).collect()
+}
+
+fn toggle_help(
+    _trigger: Trigger<ToggleHelp>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    keymap: Res<Keymap>,
+    overlays: Query<Entity, With<HelpOverlay>>,
+    camera: Single<Entity, With<MainCamera>>,
+) {
+    if !overlays.is_empty() {
+        for overlay in &overlays {
+            commands.entity(overlay).despawn();
+        }
+        return;
+    }
+    let font = TextFont {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 3.0,
+        ..default()
+    };
+    let lines = help_lines(&keymap);
+    commands
+        .spawn((
+            HelpOverlay,
+            UiTargetCamera(*camera),
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(2.0),
+                left: Val::Px(2.0),
+                display: Display::Grid,
+                grid_template_columns: vec![GridTrack::auto(), GridTrack::auto()],
+                column_gap: Val::Px(3.0),
+                padding: UiRect::all(Val::Px(2.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+        ))
+        .with_children(|parent| {
+            for (keys, description) in lines {
+                parent.spawn((Text::new(keys), font.clone()));
+                parent.spawn((Text::new(description), font.clone()));
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_combos_round_trip() {
+        for text in ["D", "Shift+W", "Ctrl+Shift+Z", "Left", "F1", "0"] {
+            let combo = KeyCombo::try_from(text.to_owned()).unwrap();
+            assert_eq!(combo.to_string(), text);
+        }
+        assert_eq!(
+            KeyCombo::try_from("ctrl+s".to_owned()),
+            Ok(KeyCombo {
+                key: KeyCode::KeyS,
+                ctrl: true,
+                shift: false
+            })
+        );
+        assert!(KeyCombo::try_from("Hyper+Q".to_owned()).is_err());
+        assert!(KeyCombo::try_from("Banana".to_owned()).is_err());
+    }
+
+    #[test]
+    fn default_keys_are_distinct() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.clashes(), []);
+        assert_eq!(
+            keymap.combo(Shortcut::PickTile(0)),
+            Some(KeyCombo::new(KeyCode::Digit1))
+        );
+        assert_eq!(
+            keymap.combo(Shortcut::PickTile(9)),
+            Some(KeyCombo::new(KeyCode::Digit0))
+        );
+    }
+
+    #[test]
+    fn shortcuts_file_overrides_defaults() {
+        let keymap = Keymap::from_ron(r#"{ Delete: "X", PanRight: "Right" }"#).unwrap();
+        assert_eq!(
+            keymap.combo(Shortcut::Delete),
+            Some(KeyCombo::new(KeyCode::KeyX))
+        );
+        assert_eq!(
+            keymap.combo(Shortcut::PanRight),
+            Some(KeyCombo::new(KeyCode::ArrowRight))
+        );
+        assert_eq!(
+            keymap.combo(Shortcut::Rewind),
+            Some(KeyCombo::new(KeyCode::KeyR))
+        );
+        assert_eq!(keymap.0.len(), Keymap::default().0.len());
+
+        assert!(Keymap::from_ron(r#"{ Delete: "Nope" }"#).is_err());
+        assert!(Keymap::from_ron(r#"{ Teleport: "X" }"#).is_err());
+    }
+}
@@ -0,0 +1,529 @@
+//! The marble simulation.
+//!
+//! This module is independent of Bevy's ECS: it works on a plain list of
+//! placed tiles and marble positions, so machines can be simulated (and
+//! tested) without opening a window. The Bevy systems in `simulate` only
+//! mirror the state of a [`Simulation`] into sprites.
+//!
+//! Each tick, every marble makes one hop:
+//! - A marble sitting at a tile input is routed through the tile, to one of
+//!   the tile's outputs or sticky points. This may change the tile's internal
+//!   state, and may release a marble resting elsewhere in the tile.
+//! - A marble sitting at a tile output crosses the tile edge, arriving at the
+//!   input of the neighboring tile (2 grid units up or down).
+//! - A marble at a sticky point stays put.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::grid::{GRID_UNITS_PER_TILE, GridPosition};
+use crate::tile::{Exit, GridExtent, IoCoord, Tile, TileState};
+
+/// A tile placed on the board.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SimTile {
+    pub tile: Tile,
+    pub extent: GridExtent,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub state: TileState,
+}
+
+impl SimTile {
+    /// Convert a tile-relative location to grid coordinates.
+    pub fn io_to_grid(&self, io: IoCoord) -> GridPosition {
+        io.to_grid(self.extent, self.flip_x, self.flip_y)
+    }
+
+    /// The input locations of this tile, in grid coordinates.
+    pub fn inputs(&self) -> impl Iterator<Item = GridPosition> + '_ {
+        self.tile.inputs().iter().map(|&io| self.io_to_grid(io))
+    }
+
+    /// The output locations of this tile, in grid coordinates.
+    pub fn outputs(&self) -> impl Iterator<Item = GridPosition> + '_ {
+        self.tile.outputs().iter().map(|&io| self.io_to_grid(io))
+    }
+
+    /// The sticky point locations of this tile, in grid coordinates.
+    pub fn sticky(&self) -> impl Iterator<Item = GridPosition> + '_ {
+        self.tile.sticky().iter().map(|&io| self.io_to_grid(io))
+    }
+}
+
+/// What a marble is currently doing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Sitting at a tile input, about to be routed through the tile.
+    Entering,
+    /// Sitting at a tile output, about to cross into the next tile.
+    Leaving,
+    /// Resting at a sticky point.
+    Resting,
+    /// Unable to move any further.
+    Blocked,
+    /// Left the board.
+    Exited,
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Entering => "entering",
+            Phase::Leaving => "leaving",
+            Phase::Resting => "resting",
+            Phase::Blocked => "blocked",
+            Phase::Exited => "exited",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SimMarble {
+    pub position: GridPosition,
+    pub phase: Phase,
+}
+
+/// Something that happened during a tick.
+///
+/// Marbles are identified by their index in [`Simulation::marbles`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SimEvent {
+    /// A marble moved to a new location.
+    Moved {
+        marble: usize,
+        from: GridPosition,
+        to: GridPosition,
+    },
+    /// A marble came to rest at a sticky point.
+    Resting {
+        marble: usize,
+        position: GridPosition,
+    },
+    /// A marble can't go any further.
+    Blocked {
+        marble: usize,
+        position: GridPosition,
+    },
+    /// A marble left the board from an output with no tile beyond it.
+    Exited {
+        marble: usize,
+        position: GridPosition,
+    },
+    /// A tile's internal state changed.
+    ///
+    /// Tiles are identified by their index in the list passed to [`Simulation::new`].
+    StateChanged { tile: usize, state: TileState },
+}
+
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    tiles: Vec<SimTile>,
+    marbles: Vec<SimMarble>,
+    /// Maps input locations to (tile index, input index).
+    inputs: HashMap<GridPosition, (usize, usize)>,
+    outputs: HashSet<GridPosition>,
+    sticky: HashSet<GridPosition>,
+}
+
+impl Simulation {
+    /// Create a new simulation from a list of tiles and marble positions.
+    ///
+    /// Marbles placed on an output will leave the tile on the first tick;
+    /// marbles placed on a sticky point stay there. A trap with a marble
+    /// placed in its pocket starts out loaded.
+    pub fn new(tiles: Vec<SimTile>, marbles: impl IntoIterator<Item = GridPosition>) -> Self {
+        let mut inputs = HashMap::new();
+        let mut outputs = HashSet::new();
+        let mut sticky = HashSet::new();
+        for (tile_index, tile) in tiles.iter().enumerate() {
+            for (input_index, pos) in tile.inputs().enumerate() {
+                inputs.insert(pos, (tile_index, input_index));
+            }
+            outputs.extend(tile.outputs());
+            sticky.extend(tile.sticky());
+        }
+
+        let mut sim = Self {
+            tiles,
+            marbles: Vec::new(),
+            inputs,
+            outputs,
+            sticky,
+        };
+        sim.marbles = marbles
+            .into_iter()
+            .map(|position| SimMarble {
+                position,
+                phase: sim.initial_phase(position),
+            })
+            .collect();
+
+        let resting: HashSet<GridPosition> = sim
+            .marbles
+            .iter()
+            .filter(|marble| marble.phase == Phase::Resting)
+            .map(|marble| marble.position)
+            .collect();
+        for tile in &mut sim.tiles {
+            if let TileState::Trap { loaded: false } = tile.state
+                && tile.sticky().any(|pos| resting.contains(&pos))
+            {
+                tile.state = TileState::Trap { loaded: true };
+            }
+        }
+        sim
+    }
+
+    /// Figure out what a marble placed at `position` should be doing.
+    fn initial_phase(&self, position: GridPosition) -> Phase {
+        if self.sticky.contains(&position) {
+            Phase::Resting
+        } else if self.outputs.contains(&position) {
+            Phase::Leaving
+        } else if self.inputs.contains_key(&position) {
+            Phase::Entering
+        } else {
+            Phase::Blocked
+        }
+    }
+
+    pub fn tiles(&self) -> &[SimTile] {
+        &self.tiles
+    }
+
+    pub fn marbles(&self) -> &[SimMarble] {
+        &self.marbles
+    }
+
+    /// Change the internal state of the tile with index `tile`.
+    pub fn set_tile_state(&mut self, tile: usize, state: TileState) {
+        self.tiles[tile].state = state;
+    }
+
+    /// Returns `true` if no marble can move any more.
+    pub fn is_quiescent(&self) -> bool {
+        self.marbles
+            .iter()
+            .all(|marble| !matches!(marble.phase, Phase::Entering | Phase::Leaving))
+    }
+
+    /// Advance the simulation by one tick.
+    pub fn tick(&mut self) -> Vec<SimEvent> {
+        let mut events = Vec::new();
+        // Marbles released from a sticky point shouldn't move again this tick.
+        let mut moved = vec![false; self.marbles.len()];
+        for index in 0..self.marbles.len() {
+            if !moved[index] {
+                self.step_marble(index, &mut moved, &mut events);
+            }
+        }
+        events
+    }
+
+    /// Tick until no marble can move, returning every event along the way.
+    ///
+    /// Returns `None` if the simulation is still going after `max_ticks`.
+    pub fn run(&mut self, max_ticks: u32) -> Option<Vec<SimEvent>> {
+        let mut events = Vec::new();
+        for _ in 0..max_ticks {
+            if self.is_quiescent() {
+                return Some(events);
+            }
+            events.extend(self.tick());
+        }
+        self.is_quiescent().then_some(events)
+    }
+
+    fn step_marble(&mut self, index: usize, moved: &mut [bool], events: &mut Vec<SimEvent>) {
+        let marble = self.marbles[index];
+        let from = marble.position;
+        let (phase, to) = match marble.phase {
+            Phase::Entering => {
+                let &(tile_index, input) = self
+                    .inputs
+                    .get(&from)
+                    .expect("entering marble isn't at an input");
+                let sim_tile = self.tiles[tile_index];
+                let Some(transition) = sim_tile.tile.transition(sim_tile.state, input) else {
+                    self.marbles[index].phase = Phase::Blocked;
+                    events.push(SimEvent::Blocked {
+                        marble: index,
+                        position: from,
+                    });
+                    return;
+                };
+
+                if transition.state != sim_tile.state {
+                    self.tiles[tile_index].state = transition.state;
+                    events.push(SimEvent::StateChanged {
+                        tile: tile_index,
+                        state: transition.state,
+                    });
+                }
+                if let Some((sticky, output)) = transition.release {
+                    let sticky_pos = sim_tile.io_to_grid(sim_tile.tile.sticky()[sticky]);
+                    let output_pos = sim_tile.io_to_grid(sim_tile.tile.outputs()[output]);
+                    self.release_marble(sticky_pos, output_pos, moved, events);
+                }
+
+                match transition.exit {
+                    Exit::Output(output) => (
+                        Phase::Leaving,
+                        sim_tile.io_to_grid(sim_tile.tile.outputs()[output]),
+                    ),
+                    Exit::Sticky(sticky) => (
+                        Phase::Resting,
+                        sim_tile.io_to_grid(sim_tile.tile.sticky()[sticky]),
+                    ),
+                }
+            }
+            Phase::Leaving => {
+                let to = next_position(from);
+                if self.inputs.contains_key(&to) {
+                    (Phase::Entering, to)
+                } else if self.tiles.iter().any(|t| t.extent.contains_grid(to)) {
+                    // There is a tile here, but it doesn't accept marbles at this location.
+                    self.marbles[index].phase = Phase::Blocked;
+                    events.push(SimEvent::Blocked {
+                        marble: index,
+                        position: from,
+                    });
+                    return;
+                } else {
+                    self.marbles[index].phase = Phase::Exited;
+                    events.push(SimEvent::Exited {
+                        marble: index,
+                        position: from,
+                    });
+                    return;
+                }
+            }
+            Phase::Resting | Phase::Blocked | Phase::Exited => return,
+        };
+
+        self.marbles[index] = SimMarble {
+            position: to,
+            phase,
+        };
+        moved[index] = true;
+        if phase == Phase::Resting {
+            events.push(SimEvent::Resting {
+                marble: index,
+                position: to,
+            });
+        } else {
+            events.push(SimEvent::Moved {
+                marble: index,
+                from,
+                to,
+            });
+        }
+    }
+
+    /// Send a marble resting at a sticky point out through a tile output.
+    fn release_marble(
+        &mut self,
+        from: GridPosition,
+        to: GridPosition,
+        moved: &mut [bool],
+        events: &mut Vec<SimEvent>,
+    ) {
+        let resting = self
+            .marbles
+            .iter()
+            .position(|m| m.phase == Phase::Resting && m.position == from);
+        if let Some(index) = resting {
+            self.marbles[index] = SimMarble {
+                position: to,
+                phase: Phase::Leaving,
+            };
+            moved[index] = true;
+            events.push(SimEvent::Moved {
+                marble: index,
+                from,
+                to,
+            });
+        }
+    }
+}
+
+/// Compute where a marble leaving a tile output will arrive.
+///
+/// Outputs in the upper half of a tile row lead to the row above; outputs in
+/// the lower half lead to the row below.
+fn next_position(output: GridPosition) -> GridPosition {
+    let GridPosition(pos) = output;
+    let row_offset = pos.y.rem_euclid(GRID_UNITS_PER_TILE);
+    let dy = if row_offset * 2 > GRID_UNITS_PER_TILE {
+        2
+    } else {
+        -2
+    };
+    GridPosition(pos.with_y(pos.y + dy))
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::ivec2;
+
+    use super::*;
+
+    fn place(tile: Tile, x: i32, y: i32) -> SimTile {
+        SimTile {
+            tile,
+            extent: tile.extent(GridPosition(ivec2(x, y))),
+            flip_x: false,
+            flip_y: false,
+            state: tile.initial_state(),
+        }
+    }
+
+    fn run(sim: &mut Simulation) -> Vec<SimEvent> {
+        sim.run(100).expect("simulation didn't finish")
+    }
+
+    #[test]
+    fn path_column() {
+        let tiles = vec![place(Tile::Path, 0, 0), place(Tile::Path, 0, 4)];
+        let mut sim = Simulation::new(tiles, [GridPosition(ivec2(2, 3))]);
+        let events = run(&mut sim);
+        assert_eq!(
+            events.last(),
+            Some(&SimEvent::Exited {
+                marble: 0,
+                position: GridPosition(ivec2(2, 7))
+            })
+        );
+    }
+
+    #[test]
+    fn path_column_ticks() {
+        let tiles = vec![place(Tile::Path, 0, 0), place(Tile::Path, 0, 4)];
+        let mut sim = Simulation::new(tiles, [GridPosition(ivec2(2, 3))]);
+        assert_eq!(sim.marbles()[0].phase, Phase::Leaving);
+
+        // Cross into the upper tile, pass through it, and leave the board.
+        assert_eq!(
+            sim.tick(),
+            [SimEvent::Moved {
+                marble: 0,
+                from: GridPosition(ivec2(2, 3)),
+                to: GridPosition(ivec2(2, 5)),
+            }]
+        );
+        assert_eq!(sim.marbles()[0].phase, Phase::Entering);
+        assert_eq!(
+            sim.tick(),
+            [SimEvent::Moved {
+                marble: 0,
+                from: GridPosition(ivec2(2, 5)),
+                to: GridPosition(ivec2(2, 7)),
+            }]
+        );
+        assert!(!sim.is_quiescent());
+        assert_eq!(
+            sim.tick(),
+            [SimEvent::Exited {
+                marble: 0,
+                position: GridPosition(ivec2(2, 7)),
+            }]
+        );
+        assert!(sim.is_quiescent());
+        assert_eq!(sim.tick(), []);
+    }
+
+    #[test]
+    fn blocked_without_input() {
+        // The shimmy above has no input where the path's output leads.
+        let tiles = vec![place(Tile::Path, 0, 0), place(Tile::Shimmy, 0, 4)];
+        let mut sim = Simulation::new(tiles, [GridPosition(ivec2(2, 3))]);
+        assert_eq!(
+            run(&mut sim),
+            [SimEvent::Blocked {
+                marble: 0,
+                position: GridPosition(ivec2(2, 3)),
+            }]
+        );
+        assert_eq!(sim.marbles()[0].phase, Phase::Blocked);
+
+        // A marble that isn't on any tile can't move at all.
+        let sim = Simulation::new(Vec::new(), [GridPosition(ivec2(2, 3))]);
+        assert_eq!(sim.marbles()[0].phase, Phase::Blocked);
+        assert!(sim.is_quiescent());
+    }
+
+    #[test]
+    fn run_gives_up() {
+        let tiles = vec![place(Tile::Path, 0, 0), place(Tile::Path, 0, 4)];
+        let mut sim = Simulation::new(tiles, [GridPosition(ivec2(2, 3))]);
+        assert_eq!(sim.run(2), None);
+        assert_eq!(sim.run(1).map(|events| events.len()), Some(1));
+    }
+
+    #[test]
+    fn next_row() {
+        assert_eq!(
+            next_position(GridPosition(ivec2(2, 3))),
+            GridPosition(ivec2(2, 5))
+        );
+        assert_eq!(
+            next_position(GridPosition(ivec2(2, 1))),
+            GridPosition(ivec2(2, -1))
+        );
+        assert_eq!(
+            next_position(GridPosition(ivec2(6, -7))),
+            GridPosition(ivec2(6, -9))
+        );
+    }
+
+    #[test]
+    fn trap_release() {
+        // Two paths feed the trap's lanes; the right one carries a marble that
+        // will be captured, the left one a marble that springs the trap one
+        // tick later.
+        let tiles = vec![
+            place(Tile::Path, 0, 0),
+            place(Tile::Path, 6, 0),
+            place(Tile::Trap, 0, 4),
+        ];
+        let marbles = [GridPosition(ivec2(8, 3)), GridPosition(ivec2(2, 1))];
+        let mut sim = Simulation::new(tiles, marbles);
+        sim.tick();
+        assert!(sim.tick().contains(&SimEvent::StateChanged {
+            tile: 2,
+            state: TileState::Trap { loaded: true }
+        }));
+        assert_eq!(sim.marbles()[0].phase, Phase::Resting);
+
+        run(&mut sim);
+        assert!(
+            sim.marbles()
+                .iter()
+                .all(|marble| marble.phase == Phase::Exited)
+        );
+        assert_eq!(sim.marbles()[0].position, GridPosition(ivec2(6, 7)));
+    }
+
+    #[test]
+    fn marble_placed_in_trap_pocket() {
+        // The marble in the pocket is released by the marble coming down the
+        // left lane, rather than sitting under a second captured marble.
+        let trap = place(Tile::Trap, 0, 4);
+        let pocket = trap.sticky().next().unwrap();
+        let tiles = vec![place(Tile::Path, 0, 0), trap];
+        let mut sim = Simulation::new(tiles, [pocket, GridPosition(ivec2(2, 1))]);
+        assert_eq!(sim.tiles()[1].state, TileState::Trap { loaded: true });
+        assert_eq!(sim.marbles()[0].phase, Phase::Resting);
+
+        run(&mut sim);
+        assert_eq!(sim.tiles()[1].state, TileState::Trap { loaded: false });
+        assert!(
+            sim.marbles()
+                .iter()
+                .all(|marble| marble.phase == Phase::Exited)
+        );
+    }
+}
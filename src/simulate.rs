@@ -0,0 +1,475 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    SimState,
+    animate::{MarbleMotion, place_marbles},
+    board::{Board, PlacedMarble},
+    grid::GridPosition,
+    save_load::{BoardEntity, spawn_board},
+    sim::{SimEvent, SimTile, Simulation},
+    tile::{GridExtent, Marble, Tile, TileState},
+};
+
+/// How long each simulation tick takes, in seconds.
+const TICK_SECONDS: f32 = 0.25;
+
+pub struct SimulatePlugin;
+
+impl Plugin for SimulatePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TickTimer(Timer::from_seconds(
+            TICK_SECONDS,
+            TimerMode::Repeating,
+        )))
+        .insert_resource(SimSpeed(1.0))
+        .add_event::<StepSimulation>()
+        .add_event::<SimTicked>()
+        .add_systems(OnEnter(SimState::Running), start_simulation)
+        .add_systems(OnEnter(SimState::Paused), start_simulation)
+        .add_observer(rewind)
+        .add_systems(
+            Update,
+            (run_simulation, animate_marbles)
+                .chain()
+                .run_if(in_state(SimState::Running)),
+        )
+        .add_systems(Update, step_simulation.run_if(in_state(SimState::Paused)))
+        .add_systems(Update, show_tile_state)
+        .add_systems(
+            Update,
+            stop_simulation.run_if(resource_exists::<ActiveSimulation>.and(not(
+                in_state(SimState::Running).or(in_state(SimState::Paused)),
+            ))),
+        );
+    }
+}
+
+/// The simulation in progress, along with the marble entities it drives.
+#[derive(Resource)]
+pub struct ActiveSimulation {
+    sim: Simulation,
+    /// Tile entities, in the same order as `Simulation::tiles`.
+    tiles: Vec<Entity>,
+    /// Marble entities, in the same order as `Simulation::marbles`.
+    marbles: Vec<Entity>,
+}
+
+impl ActiveSimulation {
+    /// Returns `true` once no marble can move.
+    pub fn is_finished(&self) -> bool {
+        self.sim.is_quiescent()
+    }
+
+    /// The simulation's index for a marble entity.
+    pub fn marble_index(&self, entity: Entity) -> Option<usize> {
+        self.marbles.iter().position(|&marble| marble == entity)
+    }
+
+    /// The marble entities, in the order of the simulation's marble indices.
+    pub fn marble_entities(&self) -> &[Entity] {
+        &self.marbles
+    }
+
+    /// Change the internal state of a tile entity in the simulation.
+    pub fn set_tile_state(&mut self, entity: Entity, state: TileState) {
+        if let Some(index) = self.tiles.iter().position(|&tile| tile == entity) {
+            self.sim.set_tile_state(index, state);
+        }
+    }
+}
+
+/// Sent after each simulation tick, with everything that happened during it.
+#[derive(Event)]
+pub struct SimTicked(pub Vec<SimEvent>);
+
+/// The board as it was when Play was pressed.
+#[derive(Resource)]
+pub struct Snapshot(pub Board);
+
+/// Times simulation ticks. Its progress through the current tick drives the
+/// marble animation.
+#[derive(Resource)]
+struct TickTimer(Timer);
+
+/// Build a new simulation from the tiles and marbles on the board.
+///
+/// If we are resuming from a pause, the existing simulation is kept.
+/// Stepping from the Idle state starts a new simulation in the Paused state.
+/// The board is saved so that it can be rewound later.
+fn start_simulation(
+    mut commands: Commands,
+    existing: Option<Res<ActiveSimulation>>,
+    tiles: Query<(Entity, &Tile, &TileState, &GridExtent, &Sprite)>,
+    marbles: Query<(Entity, &GridPosition, &Marble)>,
+    mut timer: ResMut<TickTimer>,
+) {
+    if existing.is_some() {
+        return;
+    }
+
+    let (tile_entities, sim_tiles): (Vec<Entity>, Vec<SimTile>) = tiles
+        .iter()
+        .map(|(entity, &tile, &state, &extent, sprite)| {
+            let sim_tile = SimTile {
+                tile,
+                extent,
+                flip_x: sprite.flip_x,
+                flip_y: sprite.flip_y,
+                state,
+            };
+            (entity, sim_tile)
+        })
+        .unzip();
+    let mut marbles: Vec<(Entity, PlacedMarble)> = marbles
+        .iter()
+        .map(|(entity, &position, &marble)| (entity, PlacedMarble { position, marble }))
+        .collect();
+    marbles.sort_by_key(|(_, placed)| placed.marble.id);
+    let (entities, marbles): (Vec<Entity>, Vec<PlacedMarble>) = marbles.into_iter().unzip();
+    let board = Board {
+        tiles: sim_tiles,
+        marbles,
+    };
+
+    info!("starting simulation with {} marbles", entities.len());
+    timer.0.reset();
+    commands.insert_resource(ActiveSimulation {
+        sim: board.simulation(),
+        tiles: tile_entities,
+        marbles: entities,
+    });
+    commands.insert_resource(Snapshot(board));
+}
+
+/// Advance the simulation, and move the marble sprites to match.
+fn run_simulation(
+    time: Res<Time>,
+    speed: Res<SimSpeed>,
+    mut timer: ResMut<TickTimer>,
+    active: Option<ResMut<ActiveSimulation>>,
+    mut sprites: SimSprites,
+    mut ticked: EventWriter<SimTicked>,
+    mut next_state: ResMut<NextState<SimState>>,
+) {
+    let Some(mut active) = active else {
+        return;
+    };
+    timer.0.tick(time.delta().mul_f32(speed.0));
+    for _ in 0..timer.0.times_finished_this_tick() {
+        // Stop at a tick boundary, once the last hop has finished animating.
+        if active.sim.is_quiescent() {
+            info!("simulation finished");
+            sprites.mirror_marbles(&active);
+            next_state.set(SimState::Paused);
+            return;
+        }
+        let events = active.sim.tick();
+        sprites.apply_events(&active, &events);
+        sprites.start_motions(&active, &events);
+        ticked.write(SimTicked(events));
+    }
+}
+
+/// Move the marble sprites along their hops, in step with the tick timer.
+fn animate_marbles(timer: Res<TickTimer>, mut marbles: Query<(&mut Transform, &MarbleMotion)>) {
+    place_marbles(timer.0.fraction(), &mut marbles);
+}
+
+/// Ways to advance a paused simulation.
+#[derive(Copy, Clone, Debug)]
+pub enum Step {
+    /// Advance by a single tick.
+    Tick,
+    /// Advance until something happens other than marbles rolling along.
+    UntilEvent,
+    /// Advance until no marble can move, without drawing anything in between.
+    ToCompletion,
+}
+
+/// Request to advance the simulation while paused.
+#[derive(Event)]
+pub struct StepSimulation(pub Step);
+
+/// The most ticks we will simulate for a single step request.
+const MAX_STEP_TICKS: u32 = 100_000;
+
+fn step_simulation(
+    mut step_reader: EventReader<StepSimulation>,
+    active: Option<ResMut<ActiveSimulation>>,
+    mut sprites: SimSprites,
+    mut ticked: EventWriter<SimTicked>,
+) {
+    let Some(mut active) = active else {
+        return;
+    };
+    for &StepSimulation(step) in step_reader.read() {
+        debug!("step: {step:?}");
+        for _ in 0..MAX_STEP_TICKS {
+            if active.sim.is_quiescent() {
+                break;
+            }
+            let events = active.sim.tick();
+            sprites.apply_events(&active, &events);
+            let done = match step {
+                Step::Tick => true,
+                Step::UntilEvent => events
+                    .iter()
+                    .any(|event| !matches!(event, SimEvent::Moved { .. })),
+                Step::ToCompletion => false,
+            };
+            ticked.write(SimTicked(events));
+            if done {
+                break;
+            }
+        }
+    }
+    sprites.mirror_marbles(&active);
+}
+
+/// The simulation speed multiplier.
+#[derive(Resource)]
+pub struct SimSpeed(pub f32);
+
+impl SimSpeed {
+    pub const MIN: f32 = 0.25;
+    pub const MAX: f32 = 16.0;
+
+    pub fn faster(&mut self) {
+        self.0 = (self.0 * 2.0).min(Self::MAX);
+    }
+
+    pub fn slower(&mut self) {
+        self.0 = (self.0 / 2.0).max(Self::MIN);
+    }
+
+    /// How many ticks run each second at this speed.
+    pub fn ticks_per_second(&self) -> u32 {
+        (self.0 / TICK_SECONDS).round() as u32
+    }
+}
+
+/// The entities that display the simulation state.
+#[derive(SystemParam)]
+struct SimSprites<'w, 's> {
+    commands: Commands<'w, 's>,
+    marbles: Query<
+        'w,
+        's,
+        (
+            &'static mut Transform,
+            &'static mut GridPosition,
+            &'static mut MarbleMotion,
+        ),
+        With<Marble>,
+    >,
+    tile_states: Query<'w, 's, &'static mut TileState>,
+}
+
+impl SimSprites<'_, '_> {
+    /// Update the board entities to reflect simulation events.
+    fn apply_events(&mut self, active: &ActiveSimulation, events: &[SimEvent]) {
+        for &event in events {
+            match event {
+                SimEvent::Exited { marble, position } => {
+                    debug!("marble {marble} exited at {position}");
+                    self.commands.entity(active.marbles[marble]).despawn();
+                }
+                SimEvent::Blocked { marble, position } => {
+                    debug!("marble {marble} blocked at {position}");
+                }
+                SimEvent::StateChanged { tile, state } => {
+                    if let Ok(mut tile_state) = self.tile_states.get_mut(active.tiles[tile]) {
+                        *tile_state = state;
+                    }
+                }
+                SimEvent::Moved { .. } | SimEvent::Resting { .. } => {}
+            }
+        }
+    }
+
+    /// Move the marble sprites straight to their simulated positions.
+    fn mirror_marbles(&mut self, active: &ActiveSimulation) {
+        for (sim_marble, &entity) in active.sim.marbles().iter().zip(&active.marbles) {
+            if let Ok((mut transform, mut grid_pos, mut motion)) = self.marbles.get_mut(entity) {
+                let world_pos = sim_marble.position.to_world();
+                transform.translation.x = world_pos.x;
+                transform.translation.y = world_pos.y;
+                *grid_pos = sim_marble.position;
+                *motion = MarbleMotion::stationary(sim_marble.position);
+            }
+        }
+    }
+
+    /// Set up each marble's motion for the tick that just happened.
+    ///
+    /// The sprites are moved along the way by `animate_marbles`.
+    fn start_motions(&mut self, active: &ActiveSimulation, events: &[SimEvent]) {
+        for (sim_marble, &entity) in active.sim.marbles().iter().zip(&active.marbles) {
+            if let Ok((_, mut grid_pos, mut motion)) = self.marbles.get_mut(entity) {
+                *grid_pos = sim_marble.position;
+                *motion = MarbleMotion::stationary(sim_marble.position);
+            }
+        }
+        for &event in events {
+            if let SimEvent::Moved { marble, from, to } = event
+                && let Ok((_, _, mut motion)) = self.marbles.get_mut(active.marbles[marble])
+            {
+                *motion = MarbleMotion::new(from, to);
+            }
+        }
+    }
+}
+
+/// Restore the board to the snapshot taken when Play was pressed.
+#[derive(Event)]
+pub struct Rewind;
+
+/// Despawn every tile, socket and marble, and respawn them from the snapshot.
+///
+/// The snapshot is consumed, so that the next Play takes a fresh one.
+fn rewind(
+    _trigger: Trigger<Rewind>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    snapshot: Option<Res<Snapshot>>,
+    board_entities: Query<Entity, BoardEntity>,
+) {
+    discard_simulation(&mut commands);
+    let Some(snapshot) = snapshot else {
+        return;
+    };
+
+    info!("rewind");
+    for entity in &board_entities {
+        commands.entity(entity).despawn();
+    }
+    spawn_board(&mut commands, &asset_server, &snapshot.0);
+}
+
+/// Throw away the simulation in progress, along with its snapshot.
+pub fn discard_simulation(commands: &mut Commands) {
+    commands.remove_resource::<ActiveSimulation>();
+    commands.remove_resource::<Snapshot>();
+}
+
+/// Marks the tile location that displays the tile's internal state.
+#[derive(Component)]
+pub struct StateIndicator;
+
+/// Create the state indicator that should be attached to each tile.
+pub fn state_indicator() -> impl Bundle {
+    (
+        StateIndicator,
+        Sprite::from_color(Color::WHITE, Vec2::splat(2.0)),
+        Transform::default(),
+        Visibility::Hidden,
+    )
+}
+
+/// Move each tile's state indicator to match the tile state.
+#[expect(clippy::type_complexity)]
+fn show_tile_state(
+    tiles: Query<
+        (&Tile, &TileState, &GridExtent, &Sprite, &Children),
+        Or<(Changed<TileState>, Changed<Sprite>)>,
+    >,
+    mut indicators: Query<(&mut Transform, &mut Visibility), With<StateIndicator>>,
+) {
+    for (tile, &state, &extent, sprite, children) in &tiles {
+        let mut iter = indicators.iter_many_mut(children);
+        while let Some((mut transform, mut visibility)) = iter.fetch_next() {
+            match tile.state_indicator(state) {
+                Some(io) => {
+                    // The indicator is positioned relative to the tile origin.
+                    let grid_pos = io.to_grid(extent, sprite.flip_x, sprite.flip_y);
+                    let offset = grid_pos.to_world() - extent.origin().to_world();
+                    transform.translation = offset.extend(0.5);
+                    *visibility = Visibility::Inherited;
+                }
+                None => *visibility = Visibility::Hidden,
+            }
+        }
+    }
+}
+
+/// Discard the simulation once we return to editing.
+///
+/// The snapshot goes too: edits are made to the board as the run left it, and
+/// the next Play starts from there.
+fn stop_simulation(mut commands: Commands) {
+    discard_simulation(&mut commands);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{math::ivec2, state::app::StatesPlugin};
+
+    use super::*;
+    use crate::tile::MarbleColor;
+
+    /// An app that only starts and stops simulations.
+    fn sim_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(StatesPlugin)
+            .init_state::<SimState>()
+            .insert_resource(TickTimer(Timer::from_seconds(
+                TICK_SECONDS,
+                TimerMode::Repeating,
+            )))
+            .add_systems(OnEnter(SimState::Running), start_simulation)
+            .add_systems(OnEnter(SimState::Paused), start_simulation)
+            .add_systems(
+                Update,
+                stop_simulation.run_if(resource_exists::<ActiveSimulation>.and(not(
+                    in_state(SimState::Running).or(in_state(SimState::Paused)),
+                ))),
+            );
+        app
+    }
+
+    fn set_state(app: &mut App, state: SimState) {
+        app.world_mut()
+            .resource_mut::<NextState<SimState>>()
+            .set(state);
+        app.update();
+    }
+
+    fn spawn_path(app: &mut App, x: i32) {
+        let tile = Tile::Path;
+        app.world_mut().spawn((
+            tile,
+            tile.initial_state(),
+            tile.extent(GridPosition(ivec2(x, 0))),
+            Sprite::default(),
+        ));
+    }
+
+    #[test]
+    fn play_after_editing_takes_a_new_snapshot() {
+        let mut app = sim_app();
+        spawn_path(&mut app, 0);
+        app.world_mut().spawn((
+            GridPosition(ivec2(2, 3)),
+            Marble {
+                id: 0,
+                color: MarbleColor::White,
+            },
+        ));
+
+        set_state(&mut app, SimState::Running);
+        set_state(&mut app, SimState::Paused);
+        assert_eq!(app.world().resource::<Snapshot>().0.tiles.len(), 1);
+
+        // Leaving the run to edit ends it, so there is nothing to rewind to.
+        set_state(&mut app, SimState::Placing);
+        assert!(!app.world().contains_resource::<ActiveSimulation>());
+        assert!(!app.world().contains_resource::<Snapshot>());
+        spawn_path(&mut app, 4);
+
+        // Rewinding after the next Play keeps the edit.
+        set_state(&mut app, SimState::Running);
+        let snapshot = &app.world().resource::<Snapshot>().0;
+        assert_eq!(snapshot.tiles.len(), 2);
+        assert_eq!(snapshot.marbles.len(), 1);
+    }
+}
@@ -1,4 +1,5 @@
 use bevy::{prelude::*, sprite::Anchor};
+use serde::{Deserialize, Serialize};
 
 use crate::grid::{GRID_UNITS_PER_TILE, GridPosition};
 
@@ -7,7 +8,6 @@ use crate::grid::{GRID_UNITS_PER_TILE, GridPosition};
 /// Inputs are places where marbles may enter from an adjacent tile. Outputs are
 /// locations where marbles may exit the tile. Sticky points are places where marbles
 /// may reside until perturbed by another marble.
-#[expect(dead_code)]
 struct Io {
     /// Places where marbles may enter.
     pub inputs: &'static [IoCoord],
@@ -25,7 +25,6 @@ struct Io {
 #[repr(u8)]
 enum MarbleY {
     Bottom = 1,
-    #[expect(dead_code)]
     Middle = 2,
     Top = 3,
 }
@@ -45,7 +44,7 @@ pub struct IoCoord {
     /// For a 1x1 tile, the allowed values are 1, 2, or 3. 0 and 4 are the corners,
     /// which is not allowed.
     x: u8,
-    /// The Y coordinate is always 0 (bottom side), 1 (middle), or 2 (top side).
+    /// The Y coordinate is always near the bottom side, in the middle, or near the top side.
     y: MarbleY,
 }
 
@@ -58,6 +57,14 @@ impl IoCoord {
         }
     }
 
+    /// Create an `IoCoord` halfway between the bottom and top edges of a tile.
+    const fn middle(x: u8) -> Self {
+        Self {
+            x,
+            y: MarbleY::Middle,
+        }
+    }
+
     /// Create an `IoCoord` on the top edge of a tile.
     const fn top(x: u8) -> Self {
         Self { x, y: MarbleY::Top }
@@ -80,7 +87,7 @@ impl IoCoord {
             x_direction = -1;
         }
         if flip_y {
-            y += 1;
+            y += GRID_UNITS_PER_TILE;
             y_direction = -1;
         }
 
@@ -92,38 +99,44 @@ impl IoCoord {
     }
 }
 
+/// Marbles travelling down the left lane push Canute's block across into the
+/// right lane. The next marble travelling up the right lane hits the block, is
+/// diverted to the middle exit, and knocks the block back.
 static CANUTE_IO: Io = Io {
-    inputs: &[],
+    inputs: &[IoCoord::top(2), IoCoord::bottom(6)],
     outputs: &[IoCoord::bottom(2), IoCoord::top(4), IoCoord::top(6)],
     sticky: &[],
 };
 
 static SHIMMY_IO: Io = Io {
-    inputs: &[],
+    inputs: &[IoCoord::bottom(1)],
     outputs: &[IoCoord::top(3)],
     sticky: &[],
 };
 
+/// The left lane operates the lever; the right lane may be diverted to the middle exit.
 static SWITCH_IO: Io = Io {
-    inputs: &[],
+    inputs: &[IoCoord::bottom(2), IoCoord::bottom(6)],
     outputs: &[IoCoord::top(2), IoCoord::top(4), IoCoord::top(6)],
     sticky: &[],
 };
 
 static TURN_IO: Io = Io {
-    inputs: &[],
+    inputs: &[IoCoord::bottom(2), IoCoord::bottom(6)],
     outputs: &[IoCoord::bottom(2), IoCoord::bottom(6)],
     sticky: &[],
 };
 
 static DISTRIBUTOR_IO: Io = Io {
-    inputs: &[],
+    inputs: &[IoCoord::bottom(4), IoCoord::bottom(6), IoCoord::bottom(8)],
     outputs: &[IoCoord::top(2), IoCoord::top(6), IoCoord::top(10)],
     sticky: &[],
 };
 
+/// Each outer lane turns back down the other. No lane leads to the middle
+/// output, but a marble may still be placed there.
 static LONG_TURN_IO: Io = Io {
-    inputs: &[],
+    inputs: &[IoCoord::bottom(2), IoCoord::bottom(10)],
     outputs: &[IoCoord::bottom(2), IoCoord::bottom(6), IoCoord::bottom(10)],
     sticky: &[],
 };
@@ -135,24 +148,25 @@ static PATH_IO: Io = Io {
 };
 
 static SWAP_IO: Io = Io {
-    inputs: &[],
+    inputs: &[IoCoord::bottom(2), IoCoord::bottom(6)],
     outputs: &[IoCoord::top(2), IoCoord::top(6)],
     sticky: &[],
 };
 
+/// The right lane feeds the trap pocket in the middle; the left lane releases it.
 static TRAP_IO: Io = Io {
-    inputs: &[],
+    inputs: &[IoCoord::bottom(2), IoCoord::bottom(8)],
     outputs: &[IoCoord::top(2), IoCoord::top(6), IoCoord::top(8)],
-    sticky: &[],
+    sticky: &[IoCoord::middle(6)],
 };
 
 static XOR_IO: Io = Io {
-    inputs: &[],
+    inputs: &[IoCoord::bottom(2), IoCoord::bottom(6)],
     outputs: &[IoCoord::top(2), IoCoord::top(4), IoCoord::top(6)],
     sticky: &[],
 };
 
-#[derive(Debug, Copy, Clone, Default, Component)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Component)]
 pub enum Tile {
     Canute,
     Shimmy,
@@ -196,6 +210,11 @@ impl Tile {
         }
     }
 
+    /// Look up a tile by its name.
+    pub fn from_name(name: &str) -> Option<Tile> {
+        ALL_TILES.iter().copied().find(|tile| tile.name() == name)
+    }
+
     pub fn sprite_filename(&self) -> String {
         format!("{}.png", self.name())
     }
@@ -250,8 +269,8 @@ impl Tile {
     }
 
     /// Return a list of input coordinates for this tile.
-    pub fn _inputs(&self) -> &'static [IoCoord] {
-        todo!();
+    pub fn inputs(&self) -> &'static [IoCoord] {
+        self.io().inputs
     }
 
     /// Return a list of output coordinates for this tile.
@@ -259,6 +278,125 @@ impl Tile {
         self.io().outputs
     }
 
+    /// Return a list of sticky point coordinates for this tile.
+    pub fn sticky(&self) -> &'static [IoCoord] {
+        self.io().sticky
+    }
+
+    /// Return the internal state of a newly placed tile.
+    pub fn initial_state(&self) -> TileState {
+        match self {
+            Tile::Canute | Tile::Switch => TileState::Lever { diverted: false },
+            Tile::Distributor => TileState::Distributor { next: 0 },
+            Tile::Trap => TileState::Trap { loaded: false },
+            Tile::Xor => TileState::Xor { odd: false },
+            Tile::Shimmy | Tile::Turn | Tile::LongTurn | Tile::Path | Tile::Swap => {
+                TileState::Stateless
+            }
+        }
+    }
+
+    /// Compute what happens when a marble enters at the input with index `input`,
+    /// while the tile is in state `state`.
+    ///
+    /// Returns `None` if the marble can't pass through the tile.
+    pub fn transition(&self, state: TileState, input: usize) -> Option<Transition> {
+        let exit = |exit| Transition {
+            exit,
+            state,
+            release: None,
+        };
+
+        let transition = match (self, state, input) {
+            // A marble in the left lane flips the lever.
+            (Tile::Switch, TileState::Lever { diverted }, 0) => Transition {
+                exit: Exit::Output(0),
+                state: TileState::Lever {
+                    diverted: !diverted,
+                },
+                release: None,
+            },
+            // The right lane goes to the middle exit if the lever is flipped.
+            (Tile::Switch, TileState::Lever { diverted }, 1) => {
+                exit(Exit::Output(if diverted { 1 } else { 2 }))
+            }
+            // A marble in the left lane pushes Canute's block into the right
+            // lane, where it stays however many marbles follow.
+            (Tile::Canute, TileState::Lever { .. }, 0) => Transition {
+                exit: Exit::Output(0),
+                state: TileState::Lever { diverted: true },
+                release: None,
+            },
+            // A marble in the right lane that hits the block is diverted to the
+            // middle exit, and knocks the block back out of the lane.
+            (Tile::Canute, TileState::Lever { diverted: true }, 1) => Transition {
+                exit: Exit::Output(1),
+                state: TileState::Lever { diverted: false },
+                release: None,
+            },
+            (Tile::Canute, TileState::Lever { diverted: false }, 1) => exit(Exit::Output(2)),
+            // The side lanes go straight through; the middle lane takes turns
+            // sending marbles to each output.
+            (Tile::Distributor, TileState::Distributor { .. }, 0) => exit(Exit::Output(0)),
+            (Tile::Distributor, TileState::Distributor { .. }, 2) => exit(Exit::Output(2)),
+            (Tile::Distributor, TileState::Distributor { next }, 1) => Transition {
+                exit: Exit::Output(next.into()),
+                state: TileState::Distributor {
+                    next: (next + 1) % 3,
+                },
+                release: None,
+            },
+            // The left lane springs the trap, releasing the captured marble.
+            (Tile::Trap, TileState::Trap { loaded }, 0) => Transition {
+                exit: Exit::Output(0),
+                state: TileState::Trap { loaded: false },
+                release: loaded.then_some((0, 1)),
+            },
+            // The right lane feeds the trap, if it's empty.
+            (Tile::Trap, TileState::Trap { loaded: false }, 1) => Transition {
+                exit: Exit::Sticky(0),
+                state: TileState::Trap { loaded: true },
+                release: None,
+            },
+            (Tile::Trap, TileState::Trap { loaded: true }, 1) => exit(Exit::Output(2)),
+            // Every marble toggles the Xor. Marbles that set it take the middle
+            // exit; marbles that clear it go straight through.
+            (Tile::Xor, TileState::Xor { odd }, 0 | 1) => Transition {
+                exit: if odd {
+                    Exit::Output(input * 2)
+                } else {
+                    Exit::Output(1)
+                },
+                state: TileState::Xor { odd: !odd },
+                release: None,
+            },
+            (Tile::Shimmy | Tile::Path, TileState::Stateless, 0) => exit(Exit::Output(0)),
+            (Tile::Turn | Tile::Swap, TileState::Stateless, 0) => exit(Exit::Output(1)),
+            (Tile::Turn | Tile::Swap, TileState::Stateless, 1) => exit(Exit::Output(0)),
+            (Tile::LongTurn, TileState::Stateless, 0) => exit(Exit::Output(2)),
+            (Tile::LongTurn, TileState::Stateless, 1) => exit(Exit::Output(0)),
+            _ => return None,
+        };
+        Some(transition)
+    }
+
+    /// Return the location where the tile's state should be displayed, if any.
+    ///
+    /// This marks the exit the next marble will take, or a lit lamp.
+    pub fn state_indicator(&self, state: TileState) -> Option<IoCoord> {
+        match (self, state) {
+            (Tile::Canute | Tile::Switch, TileState::Lever { diverted }) => {
+                Some(self.outputs()[if diverted { 1 } else { 2 }])
+            }
+            (Tile::Distributor, TileState::Distributor { next }) => {
+                Some(self.outputs()[usize::from(next)])
+            }
+            (Tile::Trap, TileState::Trap { loaded: true }) => Some(IoCoord::middle(10)),
+            (Tile::Xor, TileState::Xor { odd: true }) => Some(self.outputs()[1]),
+            _ => None,
+        }
+    }
+
     /// Get access to the `Io` struct for this tile.
     fn io(&self) -> &'static Io {
         match self {
@@ -276,21 +414,106 @@ impl Tile {
     }
 }
 
+/// The internal state of a placed tile.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Component, Serialize, Deserialize)]
+pub enum TileState {
+    /// The tile has no internal state.
+    #[default]
+    Stateless,
+    /// Canute and Switch: whether the lever diverts marbles to the middle exit.
+    Lever { diverted: bool },
+    /// Distributor: the output the next marble from the middle lane will take.
+    Distributor { next: u8 },
+    /// Trap: whether a marble is held in the trap.
+    Trap { loaded: bool },
+    /// Xor: whether an odd number of marbles have passed through.
+    Xor { odd: bool },
+}
+
+impl TileState {
+    /// The state that follows this one when cycling through a tile's states by hand.
+    pub fn next(self) -> Self {
+        match self {
+            TileState::Stateless => TileState::Stateless,
+            TileState::Lever { diverted } => TileState::Lever {
+                diverted: !diverted,
+            },
+            TileState::Distributor { next } => TileState::Distributor {
+                next: (next + 1) % 3,
+            },
+            TileState::Trap { loaded } => TileState::Trap { loaded: !loaded },
+            TileState::Xor { odd } => TileState::Xor { odd: !odd },
+        }
+    }
+}
+
+impl std::fmt::Display for TileState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TileState::Stateless => write!(f, "none"),
+            TileState::Lever { diverted: false } => write!(f, "lever straight"),
+            TileState::Lever { diverted: true } => write!(f, "lever diverted"),
+            TileState::Distributor { next } => write!(f, "next output {next}"),
+            TileState::Trap { loaded: false } => write!(f, "trap empty"),
+            TileState::Trap { loaded: true } => write!(f, "trap loaded"),
+            TileState::Xor { odd: false } => write!(f, "even"),
+            TileState::Xor { odd: true } => write!(f, "odd"),
+        }
+    }
+}
+
+/// The result of a marble entering a tile.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Transition {
+    /// Where the marble goes.
+    pub exit: Exit,
+    /// The new tile state.
+    pub state: TileState,
+    /// A marble resting at a sticky point (first index) is released through
+    /// an output (second index).
+    pub release: Option<(usize, usize)>,
+}
+
+/// Where a marble goes after entering a tile.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Exit {
+    /// The marble leaves through the output with this index.
+    Output(usize),
+    /// The marble comes to rest at the sticky point with this index.
+    Sticky(usize),
+}
+
 /// Which offset (horizontal alignment) a tile has.
-#[derive(Copy, Clone, Debug, Component)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Component)]
 pub enum Offset {
     Even,
     Odd,
 }
 
 /// The grid area covered by a tile.
-#[derive(Copy, Clone, Debug, Component)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Component)]
 pub struct GridExtent {
     origin: GridPosition,
     width: i32,
 }
 
 impl GridExtent {
+    /// The bottom left corner of the extent.
+    pub fn origin(&self) -> GridPosition {
+        self.origin
+    }
+
+    /// The width of the extent, in grid units.
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    /// The area covered by the extent, in world coordinates.
+    pub fn world_rect(&self) -> Rect {
+        let top_right = GridPosition(self.origin.0 + IVec2::new(self.width, GRID_UNITS_PER_TILE));
+        Rect::from_corners(self.origin.to_world(), top_right.to_world())
+    }
+
     /// Check if this extent contains a grid position.
     pub fn contains(&self, world_pos: Vec2) -> bool {
         let grid_pos = GridPosition::from_world_snap_row(world_pos);
@@ -311,6 +534,14 @@ impl GridExtent {
         true
     }
 
+    /// Check if this extent contains a grid position.
+    pub fn contains_grid(&self, grid_pos: GridPosition) -> bool {
+        let GridPosition(IVec2 { x, y }) = grid_pos;
+        let origin = self.origin.0;
+        (origin.y..origin.y + GRID_UNITS_PER_TILE).contains(&y)
+            && (origin.x..origin.x + self.width).contains(&x)
+    }
+
     /// Check if this extent intersects another extent.
     pub fn intersects(&self, other: &GridExtent) -> bool {
         debug!("intersects? {self:?} -- {other:?}");
@@ -332,8 +563,13 @@ impl GridExtent {
     }
 }
 
-#[derive(Debug, Clone, Copy, Component)]
-pub struct Marble;
+/// A marble on the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub struct Marble {
+    /// Identifies the marble in save files and traces. Unique within a board.
+    pub id: u32,
+    pub color: MarbleColor,
+}
 
 impl Marble {
     pub fn sprite_filename() -> &'static str {
@@ -345,4 +581,200 @@ impl Marble {
         sprite.anchor = Anchor::Center;
         sprite
     }
+
+    /// The next free id, after every marble in `marbles`.
+    pub fn next_id(marbles: impl IntoIterator<Item = Marble>) -> u32 {
+        marbles
+            .into_iter()
+            .map(|marble| marble.id + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// The colours that marbles come in.
+///
+/// Colours don't change how marbles move, but puzzles can route marbles of
+/// different colours to different exits.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarbleColor {
+    #[default]
+    White,
+    Red,
+    Blue,
+    Green,
+    Yellow,
+}
+
+pub const ALL_MARBLE_COLORS: &[MarbleColor] = &[
+    MarbleColor::White,
+    MarbleColor::Red,
+    MarbleColor::Blue,
+    MarbleColor::Green,
+    MarbleColor::Yellow,
+];
+
+impl MarbleColor {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MarbleColor::White => "white",
+            MarbleColor::Red => "red",
+            MarbleColor::Blue => "blue",
+            MarbleColor::Green => "green",
+            MarbleColor::Yellow => "yellow",
+        }
+    }
+
+    /// Look up a colour by its name.
+    pub fn from_name(name: &str) -> Option<MarbleColor> {
+        ALL_MARBLE_COLORS
+            .iter()
+            .copied()
+            .find(|color| color.name() == name)
+    }
+
+    /// The tint applied to the marble sprite.
+    pub fn tint(&self) -> Color {
+        match self {
+            MarbleColor::White => Color::WHITE,
+            MarbleColor::Red => Color::srgb(1.0, 0.35, 0.3),
+            MarbleColor::Blue => Color::srgb(0.4, 0.55, 1.0),
+            MarbleColor::Green => Color::srgb(0.4, 0.9, 0.4),
+            MarbleColor::Yellow => Color::srgb(1.0, 0.9, 0.3),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINS: &[IVec2] = &[
+        IVec2::new(0, 0),
+        IVec2::new(6, 4),
+        IVec2::new(-10, -8),
+        IVec2::new(-3, 12),
+    ];
+
+    const FLIPS: &[(bool, bool)] = &[(false, false), (true, false), (false, true), (true, true)];
+
+    #[test]
+    fn io_coords_inside_extent() {
+        for &tile in ALL_TILES {
+            for &origin in ORIGINS {
+                let extent = tile.extent(GridPosition(origin));
+                for &(flip_x, flip_y) in FLIPS {
+                    let io = tile
+                        .inputs()
+                        .iter()
+                        .chain(tile.outputs())
+                        .chain(tile.sticky());
+                    for io_coord in io {
+                        let grid_pos = io_coord.to_grid(extent, flip_x, flip_y);
+                        assert!(
+                            extent.contains_grid(grid_pos),
+                            "{tile:?} at {origin} flip ({flip_x}, {flip_y}): {io_coord:?} -> {grid_pos}"
+                        );
+                        // Marbles shouldn't sit on the tile's left edge.
+                        assert_ne!(grid_pos.0.x, origin.x);
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_exit(tile: Tile, exit: Exit) {
+        match exit {
+            Exit::Output(output) => assert!(output < tile.outputs().len()),
+            Exit::Sticky(sticky) => assert!(sticky < tile.sticky().len()),
+        }
+    }
+
+    #[test]
+    fn transitions_are_valid() {
+        for &tile in ALL_TILES {
+            // Feed a few marbles into each input, following the state changes.
+            for input in 0..tile.inputs().len() {
+                let mut state = tile.initial_state();
+                for _ in 0..4 {
+                    let Some(transition) = tile.transition(state, input) else {
+                        panic!("{tile:?} input {input} has no route in state {state:?}");
+                    };
+                    check_exit(tile, transition.exit);
+                    if let Some((sticky, output)) = transition.release {
+                        check_exit(tile, Exit::Sticky(sticky));
+                        check_exit(tile, Exit::Output(output));
+                    }
+                    state = transition.state;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn switch_lever() {
+        let tile = Tile::Switch;
+        let state = tile.initial_state();
+        let straight = tile.transition(state, 1).unwrap();
+        assert_eq!(straight.exit, Exit::Output(2));
+
+        let flip = tile.transition(state, 0).unwrap();
+        assert_eq!(flip.exit, Exit::Output(0));
+        let diverted = tile.transition(flip.state, 1).unwrap();
+        assert_eq!(diverted.exit, Exit::Output(1));
+    }
+
+    #[test]
+    fn canute_block() {
+        let tile = Tile::Canute;
+        let state = tile.initial_state();
+        let straight = tile.transition(state, 1).unwrap();
+        assert_eq!(straight.exit, Exit::Output(2));
+
+        // Pushing the block twice leaves it in the lane.
+        let push = tile.transition(state, 0).unwrap();
+        assert_eq!(push.exit, Exit::Output(0));
+        let push = tile.transition(push.state, 0).unwrap();
+        let blocked = tile.transition(push.state, 1).unwrap();
+        assert_eq!(blocked.exit, Exit::Output(1));
+        // The diverted marble knocks the block back.
+        assert_eq!(blocked.state, state);
+        let straight = tile.transition(blocked.state, 1).unwrap();
+        assert_eq!(straight.exit, Exit::Output(2));
+    }
+
+    #[test]
+    fn trap_capture_and_release() {
+        let tile = Tile::Trap;
+        let capture = tile.transition(tile.initial_state(), 1).unwrap();
+        assert_eq!(capture.exit, Exit::Sticky(0));
+        let pass = tile.transition(capture.state, 1).unwrap();
+        assert_eq!(pass.exit, Exit::Output(2));
+
+        let release = tile.transition(capture.state, 0).unwrap();
+        assert_eq!(release.release, Some((0, 1)));
+        assert_eq!(release.state, tile.initial_state());
+    }
+
+    #[test]
+    fn states_cycle_back() {
+        for &tile in ALL_TILES {
+            let initial = tile.initial_state();
+            let mut state = initial.next();
+            let mut steps = 1;
+            while state != initial {
+                // Every state is one the tile can be in.
+                assert!(tile.transition(state, 0).is_some());
+                state = state.next();
+                steps += 1;
+            }
+            let expected = match tile {
+                Tile::Distributor => 3,
+                _ if initial == TileState::Stateless => 1,
+                _ => 2,
+            };
+            assert_eq!(steps, expected, "{tile:?}");
+        }
+    }
 }
@@ -0,0 +1,232 @@
+//! An overlay showing the route each marble has taken during the current run.
+//!
+//! Each marble's trail is drawn in its own colour, fading as it gets older.
+//! A trail takes its marble's colour, unless other marbles share it.
+//! Clicking a marble while the simulation runs shows only its trail; clicking
+//! anywhere else shows them all again.
+
+use bevy::prelude::*;
+
+use crate::{
+    MouseClick, SimState,
+    animate::MarbleMotion,
+    sim::SimEvent,
+    simulate::{ActiveSimulation, SimTicked},
+    tile::{Marble, MarbleColor},
+};
+
+/// How many ticks it takes for a trail to fade to its faintest.
+const FADE_TICKS: u32 = 40;
+
+/// The opacity of the oldest parts of a trail.
+const MIN_ALPHA: f32 = 0.15;
+
+/// Line segments used to draw a hop that bends.
+const CURVE_SEGMENTS: u32 = 8;
+
+/// How close to a marble's centre a click must be to select it, in world pixels.
+const CLICK_RADIUS: f32 = 3.0;
+
+pub struct TrailPlugin;
+
+impl Plugin for TrailPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Trails>()
+            .add_event::<ToggleTrails>()
+            .add_observer(toggle_trails)
+            .add_systems(
+                Update,
+                (
+                    start_trails.run_if(resource_added::<ActiveSimulation>),
+                    record_trails,
+                    highlight_trail
+                        .run_if(in_state(SimState::Running).or(in_state(SimState::Paused))),
+                    draw_trails,
+                )
+                    .chain(),
+            )
+            .add_systems(OnEnter(SimState::Idle), clear_trails);
+    }
+}
+
+/// One hop along a marble's route.
+#[derive(Copy, Clone, Debug)]
+struct Hop {
+    /// The tick during which the hop happened, counting from 1.
+    tick: u32,
+    motion: MarbleMotion,
+}
+
+/// The routes taken by the marbles in the current run.
+#[derive(Resource, Default)]
+pub struct Trails {
+    /// Whether the overlay is drawn.
+    pub visible: bool,
+    /// The marble whose trail is shown on its own, if any.
+    highlighted: Option<usize>,
+    /// The hops of each marble, in the same order as the simulation's marbles.
+    hops: Vec<Vec<Hop>>,
+    /// The colour of each marble's trail, in the same order.
+    colors: Vec<Color>,
+    /// The number of ticks simulated so far.
+    ticks: u32,
+}
+
+impl Trails {
+    fn clear(&mut self) {
+        self.highlighted = None;
+        self.hops.clear();
+        self.colors.clear();
+        self.ticks = 0;
+    }
+}
+
+/// Show or hide the trail overlay.
+#[derive(Event)]
+pub struct ToggleTrails;
+
+fn toggle_trails(_trigger: Trigger<ToggleTrails>, mut trails: ResMut<Trails>) {
+    trails.visible = !trails.visible;
+    info!("trails {}", if trails.visible { "on" } else { "off" });
+}
+
+/// Forget the previous run's trails when a new run starts, and note the colour
+/// of each marble in the new run.
+fn start_trails(
+    mut trails: ResMut<Trails>,
+    active: Res<ActiveSimulation>,
+    marbles: Query<&Marble>,
+) {
+    trails.clear();
+    let colors: Vec<MarbleColor> = active
+        .marble_entities()
+        .iter()
+        .map(|&entity| {
+            marbles
+                .get(entity)
+                .map_or_else(|_| default(), |marble| marble.color)
+        })
+        .collect();
+    trails.colors = trail_colors(&colors);
+}
+
+/// The colour of each marble's trail, given the marbles' colours.
+///
+/// A marble with a colour of its own gets a trail in that colour. Marbles that
+/// share a colour get a hue each instead, so their trails can be told apart.
+fn trail_colors(colors: &[MarbleColor]) -> Vec<Color> {
+    colors
+        .iter()
+        .enumerate()
+        .map(|(marble, color)| {
+            if colors.iter().filter(|&other| other == color).count() == 1 {
+                color.tint()
+            } else {
+                // Golden-angle steps keep neighbouring marbles' colours far apart.
+                Color::hsl((marble as f32 * 137.5) % 360.0, 0.8, 0.6)
+            }
+        })
+        .collect()
+}
+
+fn clear_trails(mut trails: ResMut<Trails>) {
+    trails.clear();
+}
+
+/// Add each tick's marble movements to the trails.
+fn record_trails(mut ticked: EventReader<SimTicked>, mut trails: ResMut<Trails>) {
+    for SimTicked(events) in ticked.read() {
+        trails.ticks += 1;
+        let tick = trails.ticks;
+        for &event in events {
+            if let SimEvent::Moved { marble, from, to } = event {
+                if trails.hops.len() <= marble {
+                    trails.hops.resize_with(marble + 1, Vec::new);
+                }
+                trails.hops[marble].push(Hop {
+                    tick,
+                    motion: MarbleMotion::new(from, to),
+                });
+            }
+        }
+    }
+}
+
+/// Show only the trail of a clicked marble.
+fn highlight_trail(
+    mut clicks: EventReader<MouseClick>,
+    mut trails: ResMut<Trails>,
+    active: Option<Res<ActiveSimulation>>,
+    marbles: Query<(Entity, &Transform), With<Marble>>,
+) {
+    for click in clicks.read() {
+        if !trails.visible {
+            continue;
+        }
+        let clicked = marbles
+            .iter()
+            .find(|(_, transform)| {
+                transform.translation.truncate().distance(click.world_pos) <= CLICK_RADIUS
+            })
+            .and_then(|(entity, _)| active.as_ref()?.marble_index(entity));
+        trails.highlighted = clicked;
+    }
+}
+
+/// How opaque a hop should be, given how many ticks ago it happened.
+fn fade(age: u32) -> f32 {
+    let t = (age as f32 / FADE_TICKS as f32).min(1.0);
+    MIN_ALPHA + (1.0 - MIN_ALPHA) * (1.0 - t)
+}
+
+fn draw_trails(trails: Res<Trails>, mut gizmos: Gizmos) {
+    if !trails.visible {
+        return;
+    }
+    for (marble, hops) in trails.hops.iter().enumerate() {
+        if trails
+            .highlighted
+            .is_some_and(|highlighted| highlighted != marble)
+        {
+            continue;
+        }
+        let color = trails.colors.get(marble).copied().unwrap_or(Color::WHITE);
+        for hop in hops {
+            let color = color.with_alpha(fade(trails.ticks - hop.tick));
+            let segments = if hop.motion.is_straight() {
+                1
+            } else {
+                CURVE_SEGMENTS
+            };
+            let points = (0..=segments).map(|i| hop.motion.point(i as f32 / segments as f32));
+            gizmos.linestrip_2d(points, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trails_fade_with_age() {
+        assert_eq!(fade(0), 1.0);
+        assert!(fade(10) < fade(5));
+        assert_eq!(fade(FADE_TICKS), MIN_ALPHA);
+        assert_eq!(fade(FADE_TICKS * 2), MIN_ALPHA);
+    }
+
+    #[test]
+    fn shared_colors_get_their_own_hue() {
+        let colors = trail_colors(&[
+            MarbleColor::White,
+            MarbleColor::Red,
+            MarbleColor::White,
+            MarbleColor::Blue,
+        ]);
+        assert_eq!(colors[1], MarbleColor::Red.tint());
+        assert_eq!(colors[3], MarbleColor::Blue.tint());
+        assert_ne!(colors[0], colors[2]);
+        assert_ne!(colors[0], MarbleColor::White.tint());
+    }
+}
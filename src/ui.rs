@@ -5,11 +5,20 @@ use bevy::{
 
 use crate::{
     SimState,
-    tile::{ALL_TILES, Marble, Tile},
+    blueprint::{NameBlueprint, StampBlueprint},
+    camera::FitToView,
+    image_export::{ExportImage, ExportScale, ImageFormat, RecordRun},
+    place_marble::SelectedMarbleColor,
+    record::AnimationFormat,
+    save_load::{LoadBoard, SaveBoard},
+    shortcuts::ToggleHelp,
+    simulate::{Rewind, SimSpeed, Step, StepSimulation},
+    tile::{ALL_MARBLE_COLORS, ALL_TILES, Marble, MarbleColor, Tile},
+    trail::ToggleTrails,
 };
 
 pub const UI_PANEL_WIDTH: u32 = 780;
-pub const UI_PANEL_HEIGHT: u32 = 64;
+pub const UI_PANEL_HEIGHT: u32 = 160;
 
 pub fn init_ui(asset_server: &AssetServer, commands: &mut Commands) {
     let viewport = Viewport {
@@ -35,7 +44,7 @@ pub fn init_ui(asset_server: &AssetServer, commands: &mut Commands) {
                 top: Val::Percent(1.0),
                 left: Val::Percent(1.0),
                 width: Val::Percent(98.0),
-                height: Val::Px(16.0),
+                height: Val::Px(39.0),
                 ..default()
             },
         ))
@@ -63,9 +72,9 @@ fn buttons_panel(asset_server: &AssetServer, parent: &mut ChildSpawnerCommands)
                 width: Val::Percent(100.),
                 height: Val::Percent(100.),
                 display: Display::Flex,
-                flex_direction: FlexDirection::Row,
+                flex_direction: FlexDirection::Column,
                 justify_content: JustifyContent::Start,
-                align_items: AlignItems::Center,
+                align_items: AlignItems::Start,
                 border: UiRect::all(Val::Px(0.5)),
                 padding: UiRect::all(Val::Px(1.)),
                 ..default()
@@ -74,23 +83,257 @@ fn buttons_panel(asset_server: &AssetServer, parent: &mut ChildSpawnerCommands)
             BackgroundColor(bg_color),
         ))
         .with_children(|parent| {
-            for &tile in ALL_TILES {
-                ui_tile_button(asset_server, parent, tile.name(), tile);
-            }
-            ui_marble_button(asset_server, parent);
-            ui_action_button(asset_server, parent, "D", Action::Delete);
-            ui_action_button(asset_server, parent, "<<", Action::Rewind);
-            ui_action_button(asset_server, parent, ">", Action::Play);
-            ui_action_button(asset_server, parent, "||", Action::Pause);
+            parent.spawn(button_row()).with_children(|parent| {
+                for &tile in ALL_TILES {
+                    ui_tile_button(asset_server, parent, tile.name(), tile);
+                }
+                ui_marble_button(asset_server, parent);
+                for &color in ALL_MARBLE_COLORS {
+                    ui_marble_color_button(parent, color);
+                }
+                ui_action_button(asset_server, parent, "Sel", Action::Select);
+                ui_puzzle_text(asset_server, parent);
+            });
+            parent.spawn(button_row()).with_children(|parent| {
+                ui_action_button(asset_server, parent, "D", Action::Delete);
+                ui_action_button(asset_server, parent, "DM", Action::DeleteMarble);
+                ui_action_button(asset_server, parent, "<<", Action::Rewind);
+                ui_action_button(asset_server, parent, ">", Action::Play);
+                ui_action_button(asset_server, parent, "||", Action::Pause);
+                ui_action_button(asset_server, parent, "|>", Action::Step);
+                ui_action_button(asset_server, parent, ">|", Action::StepEvent);
+                ui_action_button(asset_server, parent, ">>", Action::FastForward);
+                ui_action_button(asset_server, parent, "-", Action::Slower);
+                ui_value_text(asset_server, parent, UiSpeedText, "1x");
+                ui_action_button(asset_server, parent, "+", Action::Faster);
+                ui_action_button(asset_server, parent, "Save", Action::Save);
+                ui_action_button(asset_server, parent, "Load", Action::Load);
+                ui_action_button(asset_server, parent, "Fit", Action::FitToView);
+                ui_action_button(asset_server, parent, "Tr", Action::ToggleTrails);
+            });
+            parent.spawn(button_row()).with_children(|parent| {
+                ui_grid_readout(asset_server, parent);
+                ui_action_button(asset_server, parent, "?", Action::Help);
+                ui_action_button(asset_server, parent, "+BP", Action::SaveBlueprint);
+                ui_action_button(asset_server, parent, "x", Action::ExportScale);
+                ui_value_text(asset_server, parent, UiExportScaleText, "");
+                ui_action_button(asset_server, parent, "PNG", Action::ExportPng);
+                ui_action_button(asset_server, parent, "SVG", Action::ExportSvg);
+                ui_action_button(asset_server, parent, "GIF", Action::RecordGif);
+                ui_action_button(asset_server, parent, "APNG", Action::RecordApng);
+                ui_blueprint_prompt(asset_server, parent);
+                parent.spawn((UiBlueprintRow, button_row()));
+            });
         });
 }
 
+/// A horizontal row of buttons in the UI panel.
+fn button_row() -> Node {
+    Node {
+        display: Display::Flex,
+        flex_direction: FlexDirection::Row,
+        justify_content: JustifyContent::Start,
+        align_items: AlignItems::Center,
+        ..default()
+    }
+}
+
 #[derive(Copy, Clone, Debug, Component)]
 pub enum Action {
     Delete,
+    DeleteMarble,
+    /// Select groups of tiles, to move or copy them.
+    Select,
     Rewind,
     Play,
     Pause,
+    /// Advance the simulation by one tick.
+    Step,
+    /// Advance the simulation until the next marble event.
+    StepEvent,
+    /// Run the simulation to completion without rendering.
+    FastForward,
+    Slower,
+    Faster,
+    Save,
+    Load,
+    /// Zoom and centre the view on the whole board.
+    FitToView,
+    /// Show or hide the marble trails.
+    ToggleTrails,
+    /// Save the selected tiles as a blueprint.
+    SaveBlueprint,
+    /// Show or hide the list of keyboard shortcuts.
+    Help,
+    /// Switch to the next scale for exported PNGs.
+    ExportScale,
+    /// Export a picture of the whole board.
+    ExportPng,
+    /// Export a vector drawing of the whole board.
+    ExportSvg,
+    /// Record a run of the board as an animated GIF.
+    RecordGif,
+    /// Record a run of the board as an animated PNG.
+    RecordApng,
+}
+
+/// Marks the text showing the simulation speed.
+#[derive(Component)]
+pub struct UiSpeedText;
+
+/// Marks the text showing the scale of exported PNGs.
+#[derive(Component)]
+pub struct UiExportScaleText;
+
+/// Create a short text display, such as the simulation speed.
+fn ui_value_text(
+    asset_server: &AssetServer,
+    parent: &mut ChildSpawnerCommands,
+    marker: impl Component,
+    initial: &str,
+) {
+    parent
+        .spawn(Node {
+            width: Val::Px(12.),
+            height: Val::Px(10.),
+            margin: UiRect::all(Val::Px(1.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                marker,
+                Text::new(initial),
+                TextFont {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 3.0,
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// Marks the text showing the puzzle status.
+#[derive(Component)]
+pub struct UiPuzzleText;
+
+/// Create the puzzle status display. It stays empty unless a puzzle is loaded.
+fn ui_puzzle_text(asset_server: &AssetServer, parent: &mut ChildSpawnerCommands) {
+    parent
+        .spawn(Node {
+            margin: UiRect::all(Val::Px(1.0)),
+            align_items: AlignItems::Center,
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                UiPuzzleText,
+                Text::default(),
+                TextFont {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 3.0,
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// Marks the text showing the grid position under the cursor.
+#[derive(Component)]
+pub struct UiGridReadout;
+
+/// Create the grid position readout.
+fn ui_grid_readout(asset_server: &AssetServer, parent: &mut ChildSpawnerCommands) {
+    parent
+        .spawn(Node {
+            width: Val::Px(20.),
+            height: Val::Px(10.),
+            margin: UiRect::all(Val::Px(1.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                UiGridReadout,
+                Text::default(),
+                TextFont {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 3.0,
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// Marks the text that shows a blueprint name as it is typed.
+#[derive(Component)]
+pub struct UiBlueprintPrompt;
+
+/// Create the blueprint name prompt. It stays empty unless a name is being typed.
+fn ui_blueprint_prompt(asset_server: &AssetServer, parent: &mut ChildSpawnerCommands) {
+    parent
+        .spawn(Node {
+            margin: UiRect::all(Val::Px(1.0)),
+            align_items: AlignItems::Center,
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                UiBlueprintPrompt,
+                Text::default(),
+                TextFont {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 3.0,
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// Marks the row that holds a button for each blueprint.
+#[derive(Component)]
+pub struct UiBlueprintRow;
+
+/// A button that starts stamping a blueprint.
+#[derive(Component)]
+pub struct UiBlueprint(pub usize);
+
+/// Create a blueprint button, captioned with the blueprint's name.
+pub fn ui_blueprint_button(
+    asset_server: &AssetServer,
+    parent: &mut ChildSpawnerCommands,
+    index: usize,
+    name: &str,
+) {
+    parent
+        .spawn((
+            UiBlueprint(index),
+            Button,
+            Node {
+                min_width: Val::Px(10.),
+                height: Val::Px(10.),
+                border: UiRect::all(Val::Px(0.5)),
+                padding: UiRect::all(Val::Px(1.0)),
+                margin: UiRect::all(Val::Px(1.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BorderColor(Color::WHITE),
+            BackgroundColor(Color::srgb(0.25, 0.25, 0.35)),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(name),
+                TextFont {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 3.0,
+                    ..default()
+                },
+            ));
+        });
 }
 
 fn ui_action_button(
@@ -158,6 +401,7 @@ fn ui_tile_button(
         ))
         .with_children(|parent| {
             parent.spawn((
+                UiTileCaption(tile),
                 Text::new(caption),
                 TextFont {
                     font: asset_server.load("fonts/FiraSans-Bold.ttf"),
@@ -202,12 +446,36 @@ fn ui_marble_button(asset_server: &AssetServer, parent: &mut ChildSpawnerCommand
         });
 }
 
+/// Create a button that selects the colour of new marbles.
+fn ui_marble_color_button(parent: &mut ChildSpawnerCommands, color: MarbleColor) {
+    parent.spawn((
+        UiMarbleColor(color),
+        Button,
+        Node {
+            width: Val::Px(5.),
+            height: Val::Px(10.),
+            border: UiRect::all(Val::Px(0.5)),
+            margin: UiRect::all(Val::Px(1.0)),
+            ..default()
+        },
+        BorderColor(Color::BLACK),
+        BackgroundColor(color.tint()),
+    ));
+}
+
 #[derive(Component)]
 pub struct UiPanelTile(Tile);
 
+/// Marks the caption of a tile button.
+#[derive(Component)]
+pub struct UiTileCaption(pub Tile);
+
 #[derive(Component)]
 pub struct UiPanelMarble;
 
+#[derive(Component)]
+pub struct UiMarbleColor(MarbleColor);
+
 #[expect(clippy::type_complexity)]
 pub fn tile_button_click(
     interaction_query: Query<
@@ -240,25 +508,164 @@ pub fn marble_button_click(
     }
 }
 
+/// Start stamping the clicked blueprint.
+#[expect(clippy::type_complexity)]
+pub fn blueprint_button_click(
+    interaction_query: Query<(&Interaction, &UiBlueprint), (Changed<Interaction>, With<Button>)>,
+    mut commands: Commands,
+) {
+    for (interaction, &UiBlueprint(index)) in &interaction_query {
+        if let Interaction::Pressed = *interaction {
+            commands.trigger(StampBlueprint(index));
+        }
+    }
+}
+
+/// Select a marble colour, and start placing marbles of that colour.
+#[expect(clippy::type_complexity)]
+pub fn marble_color_button_click(
+    interaction_query: Query<(&Interaction, &UiMarbleColor), (Changed<Interaction>, With<Button>)>,
+    mut selected: ResMut<SelectedMarbleColor>,
+    mut next_state: ResMut<NextState<SimState>>,
+) {
+    for (interaction, &UiMarbleColor(color)) in &interaction_query {
+        if let Interaction::Pressed = *interaction {
+            info!("placing {} marbles", color.name());
+            selected.0 = color;
+            next_state.set(SimState::PlacingMarbles);
+        }
+    }
+}
+
+/// Outline the button of the selected marble colour.
+pub fn show_marble_color(
+    selected: Res<SelectedMarbleColor>,
+    mut buttons: Query<(&UiMarbleColor, &mut BorderColor)>,
+) {
+    if !selected.is_changed() {
+        return;
+    }
+    for (&UiMarbleColor(color), mut border) in &mut buttons {
+        border.0 = if color == selected.0 {
+            Color::WHITE
+        } else {
+            Color::BLACK
+        };
+    }
+}
+
 #[expect(clippy::type_complexity)]
 pub fn action_button_click(
     interaction_query: Query<
         (&Interaction, &ComputedNodeTarget, &Action),
         (Changed<Interaction>, With<Button>),
     >,
-    mut _commands: Commands,
-    mut next_state: ResMut<NextState<SimState>>,
+    mut commands: Commands,
 ) {
     for (interaction, _computed_target, &action) in &interaction_query {
         if let Interaction::Pressed = *interaction {
             info!("action button: {action:?}");
-            let state = match action {
-                Action::Delete => SimState::Deleting,
-                Action::Rewind => SimState::Idle, // FIXME: needs work
-                Action::Play => SimState::Running,
-                Action::Pause => SimState::Paused,
-            };
-            next_state.set(state);
+            commands.trigger(RunAction(action));
+        }
+    }
+}
+
+/// Do what an action button does, whether it was clicked or picked with a shortcut.
+#[derive(Event)]
+pub struct RunAction(pub Action);
+
+pub fn run_action(
+    trigger: Trigger<RunAction>,
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<SimState>>,
+    mut step_writer: EventWriter<StepSimulation>,
+    mut speed: ResMut<SimSpeed>,
+    mut export_scale: ResMut<ExportScale>,
+) {
+    let RunAction(action) = *trigger;
+    let state = match action {
+        Action::Delete => SimState::Deleting,
+        Action::DeleteMarble => SimState::DeletingMarbles,
+        Action::Select => SimState::Selecting,
+        Action::Rewind => {
+            commands.trigger(Rewind);
+            SimState::Idle
+        }
+        Action::Play => SimState::Running,
+        Action::Pause => SimState::Paused,
+        Action::Step => {
+            step_writer.write(StepSimulation(Step::Tick));
+            SimState::Paused
+        }
+        Action::StepEvent => {
+            step_writer.write(StepSimulation(Step::UntilEvent));
+            SimState::Paused
+        }
+        Action::FastForward => {
+            step_writer.write(StepSimulation(Step::ToCompletion));
+            SimState::Paused
+        }
+        Action::Slower => {
+            speed.slower();
+            return;
+        }
+        Action::Faster => {
+            speed.faster();
+            return;
+        }
+        Action::Save => {
+            commands.trigger(SaveBoard);
+            return;
+        }
+        Action::Load => {
+            commands.trigger(LoadBoard);
+            return;
+        }
+        Action::FitToView => {
+            commands.trigger(FitToView);
+            return;
+        }
+        Action::ToggleTrails => {
+            commands.trigger(ToggleTrails);
+            return;
+        }
+        Action::SaveBlueprint => {
+            commands.trigger(NameBlueprint);
+            return;
+        }
+        Action::Help => {
+            commands.trigger(ToggleHelp);
+            return;
+        }
+        Action::ExportScale => {
+            export_scale.cycle();
+            return;
+        }
+        Action::ExportPng => {
+            commands.trigger(ExportImage(ImageFormat::Png));
+            return;
+        }
+        Action::ExportSvg => {
+            commands.trigger(ExportImage(ImageFormat::Svg));
+            return;
+        }
+        Action::RecordGif => {
+            commands.trigger(RecordRun(AnimationFormat::Gif));
+            return;
+        }
+        Action::RecordApng => {
+            commands.trigger(RecordRun(AnimationFormat::Apng));
+            return;
+        }
+    };
+    next_state.set(state);
+}
+
+/// Update the simulation speed display.
+pub fn show_sim_speed(speed: Res<SimSpeed>, mut text: Query<&mut Text, With<UiSpeedText>>) {
+    if speed.is_changed() {
+        for mut text in &mut text {
+            text.0 = format!("{}x", speed.0);
         }
     }
 }
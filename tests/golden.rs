@@ -0,0 +1,64 @@
+//! Golden-file tests for whole machines.
+//!
+//! Every board in `tests/golden` is simulated, and its trace (the marbles after
+//! each tick, then the final tile states) is compared with the `.trace` file
+//! next to it. After an intended change in behaviour, rewrite the expected
+//! traces with:
+//!
+//! ```text
+//! BLESS=1 cargo test --test golden
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use roonsim::{board::Board, report::trace};
+
+/// Enough ticks for any of the golden machines to finish.
+const MAX_TICKS: u32 = 1000;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn blessing() -> bool {
+    std::env::var_os("BLESS").is_some_and(|value| value != "0")
+}
+
+#[test]
+fn golden_traces() {
+    let mut boards: Vec<PathBuf> = std::fs::read_dir(golden_dir())
+        .expect("golden directory")
+        .map(|entry| entry.expect("directory entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+        .collect();
+    boards.sort();
+    assert!(!boards.is_empty(), "no golden boards found");
+
+    let mut failures = Vec::new();
+    for board_path in &boards {
+        let text = std::fs::read_to_string(board_path).unwrap();
+        let board =
+            Board::from_ron(&text).unwrap_or_else(|e| panic!("{}: {e}", board_path.display()));
+        let actual = trace(&board, MAX_TICKS);
+
+        let trace_path = board_path.with_extension("trace");
+        if blessing() {
+            std::fs::write(&trace_path, &actual).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&trace_path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{} differs from the expected trace.\n--- expected\n{expected}--- actual\n{actual}",
+                board_path.display()
+            )),
+            Err(e) => failures.push(format!("{}: {e}", trace_path.display())),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\n\nIf the new behaviour is correct, rerun with BLESS=1 to update the traces.",
+        failures.join("\n\n")
+    );
+}